@@ -1,6 +1,14 @@
 #![allow(dead_code)]
 
-#[derive(Debug, Clone, Copy)]
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{Display, Formatter},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct Pos {
     x: i32,
     y: i32,
@@ -12,87 +20,375 @@ impl Pos {
     }
 }
 
+/// The set of offsets of tiles counted as neighbors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Neighborhood {
+    /// All 8 surrounding tiles
+    Moore,
+    /// The 4 orthogonally adjacent tiles
+    VonNeumann,
+    /// A custom set of offsets, see [`Neighborhood::custom`]
+    Custom(Vec<(i32, i32)>),
+}
+
+impl Neighborhood {
+    const MOORE: [(i32, i32); 8] = [
+        (-1, -1),
+        (-1, 0),
+        (-1, 1),
+        (0, -1),
+        (0, 1),
+        (1, -1),
+        (1, 0),
+        (1, 1),
+    ];
+
+    const VON_NEUMANN: [(i32, i32); 4] = [(-1, 0), (0, -1), (0, 1), (1, 0)];
+
+    /// Creates a custom neighborhood, duplicate offsets & the tile itself are ignored.
+    ///
+    /// The offsets do not need to be symmetric, e.g. `[(0, -1)]` only counts the tile above.
+    pub fn custom(offsets: impl IntoIterator<Item = (i32, i32)>) -> Self {
+        let mut result = Vec::new();
+        for offset in offsets {
+            if offset != (0, 0) && !result.contains(&offset) {
+                result.push(offset);
+            }
+        }
+        Self::Custom(result)
+    }
+
+    pub fn offsets(&self) -> &[(i32, i32)] {
+        match self {
+            Self::Moore => &Self::MOORE,
+            Self::VonNeumann => &Self::VON_NEUMANN,
+            Self::Custom(offsets) => offsets,
+        }
+    }
+}
+
+/// Defines which rolls a forklift can access & how tiles are encoded.
 #[derive(Debug, Clone)]
-struct Floor {
-    width: i32,
-    height: i32,
-    lines: Vec<u8>,
+struct Rules {
+    neighborhood: Neighborhood,
+    /// A roll is accessible when fewer than `threshold` neighbors are rolls.
+    threshold: usize,
+    empty: u8,
+    roll: u8,
 }
 
-impl Floor {
-    const EMPTY: u8 = b'.';
-    const ROLL: u8 = b'@';
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            neighborhood: Neighborhood::Moore,
+            threshold: 4,
+            empty: b'.',
+            roll: b'@',
+        }
+    }
+}
 
-    pub fn new() -> Self {
+impl Rules {
+    pub fn new(neighborhood: Neighborhood, threshold: usize) -> Self {
         Self {
-            width: 0,
-            height: 0,
-            lines: Vec::new(),
+            neighborhood,
+            threshold,
+            ..Default::default()
         }
     }
 
-    /// Find all locations a forklift can move to.
+    /// Sets the characters used for empty tiles & rolls.
+    pub fn with_tiles(mut self, empty: u8, roll: u8) -> Self {
+        debug_assert!(empty != roll);
+        self.empty = empty;
+        self.roll = roll;
+        self
+    }
+
+    /// Returns true if a roll with the given number of neighboring rolls is accessible.
+    pub fn is_accessible(&self, count: usize) -> bool {
+        count < self.threshold
+    }
+}
+
+/// The positions of removed rolls, one entry per round.
+#[derive(Debug, Clone, Default)]
+struct History {
+    rounds: Vec<Vec<Pos>>,
+}
+
+impl History {
+    pub fn rounds(&self) -> &[Vec<Pos>] {
+        &self.rounds
+    }
+
+    /// Returns the number of removed rolls over all rounds.
+    pub fn total(&self) -> usize {
+        self.rounds.iter().map(Vec::len).sum()
+    }
+
+    /// Replays the rounds on the initial floor, returns the floor after each round.
     ///
-    /// A forklift can only be placed on a tile with a roll on it.
-    pub fn find_locations(&self) -> Vec<Pos> {
-        let mut forklifts = Vec::new();
+    /// Rolls removed in a round are marked as `x` in that round's floor.
+    pub fn replay(&self, floor: &Floor) -> Vec<Floor> {
+        let mut floor = floor.clone();
+        let mut result = Vec::new();
+
+        for round in self.rounds.iter() {
+            for &Pos { x, y } in round {
+                if let Some(tile) = floor.get_mut(x, y) {
+                    *tile = Floor::REMOVED;
+                }
+            }
+            result.push(floor.clone());
+            floor.update_floor(round.clone());
+        }
+
+        result
+    }
+
+    /// Renders the floor after each round as text.
+    pub fn frames(&self, floor: &Floor) -> Vec<String> {
+        self.replay(floor).iter().map(Floor::to_string).collect()
+    }
+
+    /// Writes the floor after each round as a numbered PPM image into the given folder.
+    pub fn write_ppm_frames(
+        &self,
+        floor: &Floor,
+        folder: &Path,
+        scale: usize,
+    ) -> anyhow::Result<()> {
+        std::fs::create_dir_all(folder)?;
+        for (round, floor) in self.replay(floor).iter().enumerate() {
+            let file = File::create(folder.join(format!("round-{:04}.ppm", round + 1)))?;
+            let mut writer = BufWriter::new(file);
+            floor.write_ppm(&mut writer, scale)?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// The common interface of all floor representations.
+///
+/// Implementors only need to provide access to single tiles, finding & removing accessible
+/// rolls is built on top of it.
+trait Layout {
+    fn rules(&self) -> &Rules;
+
+    fn width(&self) -> i32;
+
+    fn height(&self) -> i32;
+
+    /// Returns true if there is a roll at the given position.
+    fn is_roll(&self, x: i32, y: i32) -> bool;
+
+    /// Removes the roll at the given position, if any.
+    fn remove(&mut self, x: i32, y: i32);
+
+    /// Maps a position onto the floor, returns `None` if it lies outside.
+    fn locate(&self, x: i32, y: i32) -> Option<Pos> {
+        (0 <= x && x < self.width() && 0 <= y && y < self.height()).then_some(Pos::new(x, y))
+    }
+
+    /// Returns the positions of all rolls, row by row.
+    fn rolls(&self) -> Vec<Pos> {
+        let mut rolls = Vec::new();
         for y in 0..self.height() {
             for x in 0..self.width() {
-                let tile = self.get(x, y);
-                if let Some(Self::ROLL) = tile {
-                    let count = self
-                        .neighbors(x, y)
-                        .iter()
-                        .filter(|(_, tile)| *tile == Self::ROLL)
-                        .count();
-                    if count < 4 {
-                        forklifts.push(Pos::new(x, y));
-                    }
+                if self.is_roll(x, y) {
+                    rolls.push(Pos::new(x, y));
                 }
             }
         }
-        forklifts
+        rolls
+    }
+
+    /// Find all locations a forklift can move to.
+    ///
+    /// A forklift can only be placed on a tile with a roll on it.
+    fn find_locations(&self) -> Vec<Pos> {
+        self.rolls()
+            .into_iter()
+            .filter(|pos| self.rules().is_accessible(self.count_rolls(pos.x, pos.y)))
+            .collect()
+    }
+
+    /// Removes accessible rolls until no forklift can move anymore, returns the number of removed rolls.
+    fn remove_all(&mut self) -> usize {
+        self.simulate().total()
+    }
+
+    /// Removes accessible rolls round by round, returns the positions removed in each round.
+    ///
+    /// Instead of rescanning the whole floor after every round, the number of neighboring rolls is
+    /// kept per tile together with a worklist of accessible rolls. Removing a roll only updates its
+    /// neighbors, therefore the runtime is proportional to the number of removals. Rolls that become
+    /// accessible while a round is processed are only removed in the next round.
+    ///
+    /// Offsets of a custom neighborhood may be asymmetric, a removed roll therefore updates the
+    /// tiles that count it as neighbor, not the tiles it counts itself.
+    fn simulate(&mut self) -> History {
+        let width = self.width();
+        let index = move |Pos { x, y }: Pos| (y * width + x) as usize;
+        let mut counts = vec![0usize; (width * self.height()) as usize];
+
+        let queue = self
+            .rolls()
+            .into_iter()
+            .filter(|&pos| {
+                counts[index(pos)] = self.count_rolls(pos.x, pos.y);
+                self.rules().is_accessible(counts[index(pos)])
+            })
+            .collect();
+
+        remove_rounds(self, queue, |pos| {
+            counts[index(pos)] -= 1;
+            counts[index(pos)]
+        })
     }
 
     /// Mark all forklift locations as empty.
-    pub fn update_floor(&mut self, forklifts: Vec<Pos>) {
+    fn update_floor(&mut self, forklifts: Vec<Pos>) {
         for Pos { x, y } in forklifts {
-            if let Some(tile) = self.get_mut(x, y) {
-                *tile = Self::EMPTY;
+            self.remove(x, y);
+        }
+    }
+
+    /// Returns the number of rolls around the given tile.
+    fn count_rolls(&self, x: i32, y: i32) -> usize {
+        self.rules()
+            .neighborhood
+            .offsets()
+            .iter()
+            .filter(|(i, j)| self.is_roll(x + i, y + j))
+            .count()
+    }
+}
+
+/// Removes the queued rolls round by round, see [`Layout::simulate`].
+///
+/// `decrement` lowers the stored neighbor count of the given roll & returns the new count.
+fn remove_rounds<L: Layout + ?Sized>(
+    layout: &mut L,
+    mut queue: Vec<Pos>,
+    mut decrement: impl FnMut(Pos) -> usize,
+) -> History {
+    let offsets = layout.rules().neighborhood.offsets().to_vec();
+    let threshold = layout.rules().threshold;
+
+    let mut history = History::default();
+    while !queue.is_empty() {
+        let mut next = Vec::new();
+
+        for &Pos { x, y } in queue.iter() {
+            layout.remove(x, y);
+
+            for (i, j) in offsets.iter() {
+                if let Some(neighbor) = layout.locate(x - i, y - j)
+                    && layout.is_roll(neighbor.x, neighbor.y)
+                    // a roll is only queued once, when it becomes accessible
+                    && decrement(neighbor) + 1 == threshold
+                {
+                    next.push(neighbor);
+                }
             }
         }
+
+        history.rounds.push(std::mem::replace(&mut queue, next));
     }
 
-    pub fn add_row(&mut self, mut line: Vec<u8>) {
-        debug_assert!(self.width == 0 || line.len() as i32 == self.width);
-        self.width = line.len() as i32;
-        self.height += 1;
-        self.lines.append(&mut line);
+    history
+}
+
+/// How positions outside of the floor's rectangle are treated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Topology {
+    /// There are no tiles outside of the floor
+    #[default]
+    Bounded,
+    /// Coordinates wrap around at the edges, the floor forms a torus
+    Toroidal,
+}
+
+#[derive(Debug, Clone)]
+struct Floor {
+    width: i32,
+    height: i32,
+    lines: Vec<u8>,
+    rules: Rules,
+    topology: Topology,
+}
+
+impl Layout for Floor {
+    fn rules(&self) -> &Rules {
+        &self.rules
     }
 
-    pub fn width(&self) -> i32 {
+    fn width(&self) -> i32 {
         self.width
     }
 
-    pub fn height(&self) -> i32 {
+    fn height(&self) -> i32 {
         self.height
     }
 
-    const NEIGHBORS: [(i32, i32); 8] = [
-        (-1, -1),
-        (-1, 0),
-        (-1, 1),
-        (0, -1),
-        (0, 1),
-        (1, -1),
-        (1, 0),
-        (1, 1),
-    ];
+    fn is_roll(&self, x: i32, y: i32) -> bool {
+        self.get(x, y) == Some(self.rules.roll)
+    }
+
+    fn remove(&mut self, x: i32, y: i32) {
+        let empty = self.rules.empty;
+        if let Some(tile) = self.get_mut(x, y) {
+            *tile = empty;
+        }
+    }
+
+    fn locate(&self, x: i32, y: i32) -> Option<Pos> {
+        match self.topology {
+            Topology::Bounded => {
+                (0 <= x && x < self.width && 0 <= y && y < self.height).then_some(Pos::new(x, y))
+            }
+            Topology::Toroidal => (self.width > 0 && self.height > 0)
+                .then(|| Pos::new(x.rem_euclid(self.width), y.rem_euclid(self.height))),
+        }
+    }
+}
+
+impl Floor {
+    const REMOVED: u8 = b'x';
+
+    pub fn new() -> Self {
+        Self::with_rules(Rules::default())
+    }
+
+    pub fn with_rules(rules: Rules) -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            lines: Vec::new(),
+            rules,
+            topology: Topology::default(),
+        }
+    }
+
+    pub fn with_topology(mut self, topology: Topology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn add_row(&mut self, mut line: Vec<u8>) {
+        debug_assert!(self.width == 0 || line.len() as i32 == self.width);
+        self.width = line.len() as i32;
+        self.height += 1;
+        self.lines.append(&mut line);
+    }
 
     /// Returns the set of neighbors with their position & tile value.
     pub fn neighbors(&self, x: i32, y: i32) -> Vec<(Pos, u8)> {
         let mut result = Vec::new();
-        for (i, j) in Self::NEIGHBORS.iter() {
+        for (i, j) in self.rules.neighborhood.offsets() {
             let neighbor = Pos::new(x + i, y + j);
             if let Some(tile) = self.get(neighbor.x, neighbor.y) {
                 result.push((neighbor, tile));
@@ -101,35 +397,384 @@ impl Floor {
         result
     }
 
+    fn index(&self, x: i32, y: i32) -> usize {
+        (y * self.width + x) as usize
+    }
+
     fn get(&self, x: i32, y: i32) -> Option<u8> {
-        if 0 <= x && x < self.width as i32 && 0 <= y && y < self.height as i32 {
-            Some(self.lines[(y * self.width + x) as usize])
+        let Pos { x, y } = self.locate(x, y)?;
+        Some(self.lines[self.index(x, y)])
+    }
+
+    fn get_mut(&mut self, x: i32, y: i32) -> Option<&mut u8> {
+        let Pos { x, y } = self.locate(x, y)?;
+        let index = self.index(x, y);
+        self.lines.get_mut(index)
+    }
+
+    /// Writes the floor as binary PPM image, each tile is drawn as a square of `scale` pixels.
+    pub fn write_ppm(&self, out: &mut impl Write, scale: usize) -> anyhow::Result<()> {
+        let scale = scale.max(1);
+        writeln!(
+            out,
+            "P6\n{} {}\n255",
+            self.width as usize * scale,
+            self.height as usize * scale
+        )?;
+
+        for y in 0..self.height() {
+            let row = (0..self.width())
+                .flat_map(|x| {
+                    let color = match self.get(x, y) {
+                        Some(tile) if tile == self.rules.roll => [230, 230, 230],
+                        Some(Self::REMOVED) => [220, 40, 40],
+                        _ => [30, 30, 30],
+                    };
+                    std::iter::repeat_n(color, scale).flatten()
+                })
+                .collect::<Vec<u8>>();
+            for _ in 0..scale {
+                out.write_all(&row)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Floor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for row in self.lines.chunks(self.width.max(1) as usize) {
+            writeln!(f, "{}", String::from_utf8_lossy(row))?;
+        }
+        Ok(())
+    }
+}
+
+/// A floor storing a single bit per tile, a set bit marks a roll.
+///
+/// Each row is padded to a multiple of 64 tiles, unused bits are always zero. Neighbor counts
+/// are computed for 64 tiles at once by shifting rows by the neighbor offsets and adding them
+/// into bit-sliced counters, both for finding locations and for the initial counts of a
+/// simulation. Removals during a simulation update single tiles. Only bounded floors can be
+/// converted, toroidal ones are rejected.
+#[derive(Debug, Clone)]
+struct BitFloor {
+    width: i32,
+    height: i32,
+    /// Number of words per row
+    words: usize,
+    bits: Vec<u64>,
+    rules: Rules,
+}
+
+impl TryFrom<&Floor> for BitFloor {
+    type Error = anyhow::Error;
+
+    fn try_from(floor: &Floor) -> anyhow::Result<Self> {
+        if floor.topology != Topology::Bounded {
+            anyhow::bail!("BitFloor only supports bounded floors");
+        }
+
+        let words = (floor.width as usize).div_ceil(64);
+        let mut bits = vec![0u64; words * floor.height as usize];
+        for y in 0..floor.height {
+            for x in 0..floor.width {
+                if floor.is_roll(x, y) {
+                    bits[y as usize * words + x as usize / 64] |= 1 << (x % 64);
+                }
+            }
+        }
+
+        Ok(Self {
+            width: floor.width,
+            height: floor.height,
+            words,
+            bits,
+            rules: floor.rules.clone(),
+        })
+    }
+}
+
+impl Layout for BitFloor {
+    fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    fn width(&self) -> i32 {
+        self.width
+    }
+
+    fn height(&self) -> i32 {
+        self.height
+    }
+
+    fn is_roll(&self, x: i32, y: i32) -> bool {
+        if 0 <= x && x < self.width && 0 <= y && y < self.height {
+            self.row(y)[x as usize / 64] & (1 << (x % 64)) != 0
         } else {
-            None
+            false
         }
     }
 
-    fn get_mut(&mut self, x: i32, y: i32) -> Option<&mut u8> {
-        if 0 <= x && x < self.width as i32 && 0 <= y && y < self.height as i32 {
-            self.lines.get_mut((y * self.width + x) as usize)
+    fn remove(&mut self, x: i32, y: i32) {
+        if 0 <= x && x < self.width && 0 <= y && y < self.height {
+            self.bits[y as usize * self.words + x as usize / 64] &= !(1 << (x % 64));
+        }
+    }
+
+    /// Find all locations a forklift can move to, 64 tiles at a time.
+    fn find_locations(&self) -> Vec<Pos> {
+        let mut forklifts = Vec::new();
+        let mut planes = self.planes();
+        for y in 0..self.height {
+            for word in 0..self.words {
+                let rolls = self.row(y)[word];
+                if rolls == 0 {
+                    continue;
+                }
+
+                self.count_word(y, word, &mut planes);
+                let mut accessible = rolls & less_than(&planes, self.rules.threshold);
+                while accessible != 0 {
+                    let bit = accessible.trailing_zeros() as i32;
+                    forklifts.push(Pos::new(word as i32 * 64 + bit, y));
+                    accessible &= accessible - 1;
+                }
+            }
+        }
+        forklifts
+    }
+
+    /// Counts the neighbors of all rolls 64 tiles at a time, then removes rolls tile by tile.
+    fn simulate(&mut self) -> History {
+        let width = self.width;
+        let index = move |Pos { x, y }: Pos| (y * width + x) as usize;
+        let mut counts = vec![0usize; (width * self.height) as usize];
+
+        let mut queue = Vec::new();
+        let mut planes = self.planes();
+        for y in 0..self.height {
+            for word in 0..self.words {
+                let mut rolls = self.row(y)[word];
+                if rolls == 0 {
+                    continue;
+                }
+
+                self.count_word(y, word, &mut planes);
+                while rolls != 0 {
+                    let bit = rolls.trailing_zeros();
+                    let pos = Pos::new(word as i32 * 64 + bit as i32, y);
+                    counts[index(pos)] = planes
+                        .iter()
+                        .enumerate()
+                        .map(|(k, plane)| ((plane >> bit) & 1) as usize * (1 << k))
+                        .sum();
+                    if self.rules.is_accessible(counts[index(pos)]) {
+                        queue.push(pos);
+                    }
+                    rolls &= rolls - 1;
+                }
+            }
+        }
+
+        remove_rounds(self, queue, |pos| {
+            counts[index(pos)] -= 1;
+            counts[index(pos)]
+        })
+    }
+}
+
+impl BitFloor {
+    fn row(&self, y: i32) -> &[u64] {
+        let start = y as usize * self.words;
+        &self.bits[start..start + self.words]
+    }
+
+    /// Returns empty bit-sliced counters, with enough planes for all neighbors & the threshold.
+    fn planes(&self) -> Vec<u64> {
+        let max_count = self
+            .rules
+            .neighborhood
+            .offsets()
+            .len()
+            .max(self.rules.threshold);
+        vec![0u64; (usize::BITS - max_count.leading_zeros()).max(1) as usize]
+    }
+
+    /// Counts the neighboring rolls of the 64 tiles of the word into the bit-sliced counters.
+    fn count_word(&self, y: i32, word: usize, planes: &mut [u64]) {
+        planes.fill(0);
+        for (i, j) in self.rules.neighborhood.offsets() {
+            if 0 <= y + j && y + j < self.height {
+                add_bits(planes, shifted(self.row(y + j), word, *i));
+            }
+        }
+    }
+}
+
+/// Returns the 64 bits of the row starting at column `64 * word + offset`, bits outside the row are zero.
+fn shifted(row: &[u64], word: usize, offset: i32) -> u64 {
+    let get = |index: i64| {
+        if 0 <= index && index < row.len() as i64 {
+            row[index as usize]
         } else {
-            None
+            0
         }
+    };
+
+    let column = word as i64 * 64 + offset as i64;
+    let (index, shift) = (column.div_euclid(64), column.rem_euclid(64));
+    if shift == 0 {
+        get(index)
+    } else {
+        (get(index) >> shift) | (get(index + 1) << (64 - shift))
     }
 }
 
+/// Adds a single bit per column to the bit-sliced counters, `planes[k]` holds bit `k` of each count.
+fn add_bits(planes: &mut [u64], mut carry: u64) {
+    for plane in planes.iter_mut() {
+        let next = *plane & carry;
+        *plane ^= carry;
+        carry = next;
+    }
+}
+
+/// Returns the columns whose bit-sliced count is less than `threshold`.
+fn less_than(planes: &[u64], threshold: usize) -> u64 {
+    let mut less = 0;
+    let mut equal = !0;
+    for (k, plane) in planes.iter().enumerate().rev() {
+        if threshold & (1 << k) != 0 {
+            less |= equal & !plane;
+            equal &= plane;
+        } else {
+            equal &= !plane;
+        }
+    }
+    less
+}
+
+/// A floor only storing the positions of rolls, meant for large mostly empty layouts.
+///
+/// The floor is unbounded, `width` & `height` only describe the extent of the inserted rolls.
+#[derive(Debug, Clone)]
+struct SparseFloor {
+    width: i32,
+    height: i32,
+    rolls: HashSet<Pos>,
+    rules: Rules,
+}
+
+impl From<&Floor> for SparseFloor {
+    fn from(floor: &Floor) -> Self {
+        let mut sparse = SparseFloor::new(floor.rules.clone());
+        for pos in floor.rolls() {
+            sparse.insert(pos);
+        }
+        sparse
+    }
+}
+
+impl Layout for SparseFloor {
+    fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    fn width(&self) -> i32 {
+        self.width
+    }
+
+    fn height(&self) -> i32 {
+        self.height
+    }
+
+    fn is_roll(&self, x: i32, y: i32) -> bool {
+        self.rolls.contains(&Pos::new(x, y))
+    }
+
+    fn remove(&mut self, x: i32, y: i32) {
+        self.rolls.remove(&Pos::new(x, y));
+    }
+
+    fn locate(&self, x: i32, y: i32) -> Option<Pos> {
+        Some(Pos::new(x, y))
+    }
+
+    fn rolls(&self) -> Vec<Pos> {
+        let mut rolls = self.rolls.iter().cloned().collect::<Vec<_>>();
+        rolls.sort_by_key(|pos| (pos.y, pos.x));
+        rolls
+    }
+
+    /// Same as [`Layout::simulate`], but only keeps neighbor counts for rolls.
+    fn simulate(&mut self) -> History {
+        let mut counts = HashMap::new();
+        let queue = self
+            .rolls()
+            .into_iter()
+            .filter(|&pos| {
+                let count = self.count_rolls(pos.x, pos.y);
+                counts.insert(pos, count);
+                self.rules.is_accessible(count)
+            })
+            .collect();
+
+        remove_rounds(self, queue, |pos| {
+            let count = counts.get_mut(&pos).expect("Failed to find roll");
+            *count -= 1;
+            *count
+        })
+    }
+}
+
+impl SparseFloor {
+    pub fn new(rules: Rules) -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            rolls: HashSet::new(),
+            rules,
+        }
+    }
+
+    pub fn insert(&mut self, pos: Pos) {
+        self.width = self.width.max(pos.x + 1);
+        self.height = self.height.max(pos.y + 1);
+        self.rolls.insert(pos);
+    }
+}
+
+/// Parses the rolls of a floor without storing empty tiles.
+fn parse_sparse(input: &str, rules: Rules) -> SparseFloor {
+    let mut floor = SparseFloor::new(rules);
+    let lines = input.lines().map(str::trim).filter(|line| !line.is_empty());
+    for (y, line) in lines.enumerate() {
+        for (x, tile) in line.bytes().enumerate() {
+            if tile == floor.rules.roll {
+                floor.insert(Pos::new(x as i32, y as i32));
+            }
+        }
+    }
+    floor
+}
+
 fn parse_input(input: &str) -> Floor {
-    let floor = input
+    parse_input_with(input, Rules::default())
+}
+
+/// Parses the floor, tiles are interpreted by the given rules.
+fn parse_input_with(input: &str, rules: Rules) -> Floor {
+    input
         .lines()
         .map(str::trim)
         .filter(|line| !line.is_empty())
         .map(|line| line.as_bytes())
-        .fold(Floor::new(), |mut floor, row| {
+        .fold(Floor::with_rules(rules), |mut floor, row| {
             floor.add_row(row.to_vec());
             floor
-        });
-
-    floor
+        })
 }
 
 fn process_part1(floor: Floor) -> usize {
@@ -137,6 +782,11 @@ fn process_part1(floor: Floor) -> usize {
 }
 
 fn process_part2(mut floor: Floor) -> usize {
+    floor.remove_all()
+}
+
+/// Removes rolls round by round, rescanning the whole floor each time.
+fn process_part2_rounds(mut floor: impl Layout) -> usize {
     let mut total = 0;
 
     loop {
@@ -162,7 +812,12 @@ fn main() {
 
 #[cfg(test)]
 mod tests {
-    use crate::{parse_input, process_part1, process_part2};
+    use std::time::Instant;
+
+    use crate::{
+        BitFloor, Layout, Neighborhood, Pos, Rules, SparseFloor, Topology, parse_input,
+        parse_input_with, parse_sparse, process_part1, process_part2, process_part2_rounds,
+    };
 
     const INPUT: &str = r#"
         ..@@.@@@@.
@@ -195,4 +850,180 @@ mod tests {
         let floor = parse_input(INPUT);
         assert_eq!(43, process_part2(floor));
     }
+
+    #[test]
+    fn check_part2_matches_rounds() {
+        let floor = parse_input(INPUT);
+        assert_eq!(process_part2_rounds(floor.clone()), process_part2(floor));
+
+        let floor = parse_input(&generate_floor(120, 80, 0x2025));
+        assert_eq!(process_part2_rounds(floor.clone()), process_part2(floor));
+    }
+
+    #[test]
+    fn test_history() {
+        let floor = parse_input(INPUT);
+        let history = floor.clone().simulate();
+        let rounds = history.rounds().iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(vec![13, 12, 7, 5, 2, 1, 1, 1, 1], rounds);
+        assert_eq!(43, history.total());
+
+        let frames = history.frames(&floor);
+        assert_eq!(9, frames.len());
+        assert_eq!(
+            "..xx.xx@x.\n\
+             x@@.@.@.@@\n\
+             @@@@@.x.@@\n\
+             @.@@@@..@.\n\
+             x@.@@@@.@x\n\
+             .@@@@@@@.@\n\
+             .@.@.@.@@@\n\
+             x.@@@.@@@@\n\
+             .@@@@@@@@.\n\
+             x.x.@@@.x.\n",
+            frames[0]
+        );
+    }
+
+    #[test]
+    fn test_write_ppm() {
+        let floor = parse_input(INPUT);
+        let mut out = Vec::new();
+        floor.write_ppm(&mut out, 2).unwrap();
+        let header = b"P6\n20 20\n255\n";
+        assert!(out.starts_with(header));
+        assert_eq!(header.len() + 20 * 20 * 3, out.len());
+    }
+
+    #[test]
+    fn test_neighborhoods() {
+        let rules = Rules::new(Neighborhood::VonNeumann, 2);
+        let floor = parse_input_with(INPUT, rules.clone());
+        assert_eq!(
+            vec![(-1, 0), (0, -1), (0, 1), (1, 0)],
+            floor.rules().neighborhood.offsets()
+        );
+        assert_eq!(process_part2_rounds(floor.clone()), process_part2(floor));
+
+        // only count the tiles to the left & above, which is not symmetric
+        let neighborhood = Neighborhood::custom([(-1, 0), (0, -1), (0, 0), (-1, 0)]);
+        assert_eq!(&[(-1, 0), (0, -1)], neighborhood.offsets());
+        let floor = parse_input_with(&generate_floor(60, 40, 7), Rules::new(neighborhood, 2));
+        assert_eq!(process_part2_rounds(floor.clone()), process_part2(floor));
+    }
+
+    #[test]
+    fn test_custom_tiles() {
+        let input = INPUT.replace('.', "-").replace('@', "#");
+        let floor = parse_input_with(&input, Rules::default().with_tiles(b'-', b'#'));
+        assert_eq!(13, process_part1(floor.clone()));
+        assert_eq!(43, process_part2(floor));
+    }
+
+    #[test]
+    fn test_bit_floor() {
+        let floor = parse_input(INPUT);
+        let bits = BitFloor::try_from(&floor).unwrap();
+        assert_eq!(floor.find_locations(), bits.find_locations());
+        assert_eq!(43, bits.clone().remove_all());
+
+        // rows spanning multiple words, with all kinds of neighborhoods
+        let input = generate_floor(150, 70, 99);
+        let neighborhoods = [
+            Neighborhood::Moore,
+            Neighborhood::VonNeumann,
+            Neighborhood::custom([(-70, 0), (65, 1), (2, -2), (-1, 0)]),
+        ];
+        for neighborhood in neighborhoods {
+            for threshold in [0, 2, 3, 4, 9] {
+                let floor = parse_input_with(&input, Rules::new(neighborhood.clone(), threshold));
+                let bits = BitFloor::try_from(&floor).unwrap();
+                assert_eq!(floor.find_locations(), bits.find_locations());
+                assert_eq!(
+                    floor.clone().simulate().rounds(),
+                    bits.clone().simulate().rounds()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_toroidal_floor() {
+        // without edges every roll of a full floor has 8 neighbors
+        let floor = parse_input("@@@\n@@@\n@@@");
+        assert_eq!(4, floor.find_locations().len());
+        let floor = floor.with_topology(Topology::Toroidal);
+        assert_eq!(0, floor.find_locations().len());
+        assert_eq!(0, floor.clone().remove_all());
+        assert!(BitFloor::try_from(&floor).is_err());
+
+        let floor = parse_input(INPUT).with_topology(Topology::Toroidal);
+        assert_eq!(Some(Pos::new(9, 0)), floor.locate(-1, 10));
+        assert_eq!(process_part2_rounds(floor.clone()), process_part2(floor));
+
+        let floor = parse_input(&generate_floor(50, 30, 3)).with_topology(Topology::Toroidal);
+        assert_eq!(process_part2_rounds(floor.clone()), process_part2(floor));
+    }
+
+    #[test]
+    fn test_sparse_floor() {
+        let floor = parse_sparse(INPUT, Rules::default());
+        assert_eq!(10, floor.width());
+        assert_eq!(13, floor.find_locations().len());
+        assert_eq!(43, floor.clone().remove_all());
+
+        let floor = parse_input(&generate_floor(80, 60, 11));
+        let sparse = SparseFloor::from(&floor);
+        assert_eq!(floor.find_locations(), sparse.find_locations());
+        assert_eq!(
+            floor.clone().simulate().rounds(),
+            sparse.clone().simulate().rounds()
+        );
+
+        // rolls far apart from each other
+        let mut sparse = SparseFloor::new(Rules::default());
+        for pos in [(-1_000_000, 5), (-1_000_000, 6), (2_000_000_000, -7)] {
+            sparse.insert(Pos::new(pos.0, pos.1));
+        }
+        assert_eq!(3, sparse.remove_all());
+    }
+
+    /// Compares both floor representations, run with `cargo test --release -- --ignored --nocapture`
+    ///
+    /// A manual benchmark printing its timings, the repository has no bench harness.
+    #[test]
+    #[ignore]
+    fn bench_bit_floor() {
+        let floor = parse_input(&generate_floor(4000, 4000, 1234));
+        let bits = BitFloor::try_from(&floor).unwrap();
+
+        let now = Instant::now();
+        let locations = floor.find_locations().len();
+        println!("Floor::find_locations: {:?}", now.elapsed());
+        let now = Instant::now();
+        assert_eq!(locations, bits.find_locations().len());
+        println!("BitFloor::find_locations: {:?}", now.elapsed());
+
+        let now = Instant::now();
+        let removed = floor.clone().remove_all();
+        println!("Floor::remove_all: {:?}", now.elapsed());
+        let now = Instant::now();
+        assert_eq!(removed, bits.clone().remove_all());
+        println!("BitFloor::remove_all: {:?}", now.elapsed());
+    }
+
+    /// Generates a floor of the given size with pseudo random rolls.
+    fn generate_floor(width: usize, height: usize, mut seed: u64) -> String {
+        let mut input = String::new();
+        for _ in 0..height {
+            for _ in 0..width {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                input.push(if seed.is_multiple_of(4) { '.' } else { '@' });
+            }
+            input.push('\n');
+        }
+        input
+    }
 }
@@ -1,6 +1,15 @@
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+};
+
+use anyhow::{Context, anyhow, bail};
+
 type Id = u64;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct Range {
     min: Id,
     max: Id,
@@ -16,68 +25,537 @@ impl Range {
         self.min <= value && value <= self.max
     }
 
+    /// Returns true if all Ids of the other range are in this range.
+    pub fn contains_range(&self, other: &Range) -> bool {
+        self.min <= other.min && other.max <= self.max
+    }
+
+    /// Returns true if both ranges share at least one Id.
     pub fn overlaps(&self, other: &Range) -> bool {
-        self.contains(other.min) || self.contains(other.max)
+        self.min <= other.max && other.min <= self.max
+    }
+
+    /// Returns true if both ranges overlap or are adjacent, e.g. 3-5 and 6-8
+    pub fn touches(&self, other: &Range) -> bool {
+        self.min <= other.max.saturating_add(1) && other.min <= self.max.saturating_add(1)
     }
 
     pub fn merge(&mut self, other: &Range) {
-        debug_assert!(self.overlaps(other));
+        debug_assert!(self.touches(other));
         self.min = std::cmp::min(self.min, other.min);
         self.max = std::cmp::max(self.max, other.max);
     }
 
-    /// Returns count of Ids in range (inclusive)
-    pub fn count(&self) -> u64 {
-        self.max - self.min + 1
+    /// Returns count of Ids in range (inclusive), the full range of Ids does not fit into `u64`
+    pub fn count(&self) -> u128 {
+        (self.max - self.min) as u128 + 1
     }
 }
 
-/// Parses ranges and ingredients
-///
-/// NOTE: ranges can consist of a single element, e.g. 11-11
-fn parse_input(input: &str) -> (Vec<Range>, Vec<Id>) {
+/// A set of Ids, stored as sorted, disjoint & coalesced ranges.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct RangeSet {
+    ranges: Vec<Range>,
+}
+
+impl FromIterator<Range> for RangeSet {
+    /// Sorts all ranges first, then coalesces them in a single pass.
+    fn from_iter<T: IntoIterator<Item = Range>>(iter: T) -> Self {
+        let mut ranges = iter.into_iter().collect::<Vec<_>>();
+        ranges.sort_unstable_by_key(|range| range.min);
+
+        let mut set = RangeSet::new();
+        for range in ranges {
+            match set.ranges.last_mut() {
+                Some(last) if last.touches(&range) => last.merge(&range),
+                _ => set.ranges.push(range),
+            }
+        }
+        set
+    }
+}
+
+impl RangeSet {
+    pub fn new() -> Self {
+        Self { ranges: Vec::new() }
+    }
+
+    pub fn ranges(&self) -> &[Range] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Returns true if the Id is in the set, using a binary search over the ranges.
+    pub fn contains(&self, value: Id) -> bool {
+        let index = self.ranges.partition_point(|range| range.max < value);
+        self.ranges
+            .get(index)
+            .is_some_and(|range| range.contains(value))
+    }
+
+    /// Checks a stream of ascending Ids in a single pass over the ranges.
+    ///
+    /// Returns for each Id whether it is in the set.
+    pub fn contains_sorted<'a>(
+        &'a self,
+        values: impl IntoIterator<Item = Id> + 'a,
+    ) -> impl Iterator<Item = bool> + 'a {
+        let mut ranges = self.ranges.iter().peekable();
+        let mut previous = Id::MIN;
+        values.into_iter().map(move |value| {
+            debug_assert!(previous <= value, "Ids must be sorted");
+            previous = value;
+            while ranges.next_if(|range| range.max < value).is_some() {}
+            ranges.peek().is_some_and(|range| range.contains(value))
+        })
+    }
+
+    /// Returns count of all Ids in the set
+    pub fn count(&self) -> u128 {
+        self.ranges.iter().map(Range::count).sum()
+    }
+
+    /// Adds all Ids of the range, merges it with all overlapping or adjacent ranges.
+    pub fn insert(&mut self, range: Range) {
+        let start = self
+            .ranges
+            .partition_point(|other| other.max.saturating_add(1) < range.min);
+        let end = self
+            .ranges
+            .partition_point(|other| other.min <= range.max.saturating_add(1));
+
+        let mut merged = range;
+        if start < end {
+            merged.merge(&self.ranges[start]);
+            merged.merge(&self.ranges[end - 1]);
+        }
+        self.ranges.splice(start..end, [merged]);
+    }
+
+    /// Removes all Ids of the range, ranges partially covered are cut or split.
+    pub fn remove(&mut self, range: &Range) {
+        let start = self.ranges.partition_point(|other| other.max < range.min);
+        let end = self.ranges.partition_point(|other| other.min <= range.max);
+
+        let mut remaining = Vec::new();
+        if start < end {
+            let (first, last) = (&self.ranges[start], &self.ranges[end - 1]);
+            if first.min < range.min {
+                remaining.push(Range::new(first.min, range.min - 1));
+            }
+            if range.max < last.max {
+                remaining.push(Range::new(range.max + 1, last.max));
+            }
+        }
+        self.ranges.splice(start..end, remaining);
+    }
+
+    /// Returns all Ids in either set.
+    pub fn union(&self, other: &RangeSet) -> RangeSet {
+        let mut result = self.clone();
+        for range in other.ranges.iter() {
+            result.insert(range.clone());
+        }
+        result
+    }
+
+    /// Returns all Ids in both sets.
+    pub fn intersection(&self, other: &RangeSet) -> RangeSet {
+        let mut result = RangeSet::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.ranges.len() && j < other.ranges.len() {
+            let (left, right) = (&self.ranges[i], &other.ranges[j]);
+            if left.overlaps(right) {
+                let min = std::cmp::max(left.min, right.min);
+                let max = std::cmp::min(left.max, right.max);
+                result.ranges.push(Range::new(min, max));
+            }
+            if left.max < right.max {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        result
+    }
+
+    /// Returns all Ids in this set, but not in the other.
+    pub fn difference(&self, other: &RangeSet) -> RangeSet {
+        let mut result = self.clone();
+        for range in other.ranges.iter() {
+            result.remove(range);
+        }
+        result
+    }
+}
+
+/// A range from the input together with its line number, starting at 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Source {
+    line: usize,
+    range: Range,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}: {}-{}",
+            self.line, self.range.min, self.range.max
+        )
+    }
+}
+
+/// Describes why an ingredient is fresh or spoiled.
+#[derive(Debug, PartialEq, Eq)]
+enum Explanation<'a> {
+    /// All input ranges containing the ingredient
+    Fresh(Vec<&'a Source>),
+    /// The nearest input ranges below & above the ingredient
+    Spoiled {
+        below: Option<&'a Source>,
+        above: Option<&'a Source>,
+    },
+}
+
+impl Display for Explanation<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let format = |source: Option<&Source>| match source {
+            Some(source) => source.to_string(),
+            None => "none".to_string(),
+        };
+
+        match self {
+            Explanation::Fresh(sources) => {
+                write!(f, "fresh, contained in ")?;
+                for (index, source) in sources.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "[{}]", source)?;
+                }
+                Ok(())
+            }
+            Explanation::Spoiled { below, above } => write!(
+                f,
+                "spoiled, nearest below [{}], nearest above [{}]",
+                format(*below),
+                format(*above)
+            ),
+        }
+    }
+}
+
+/// Coverage statistics of the fresh ranges within a window of Ids.
+#[derive(Debug)]
+struct Coverage<'a> {
+    window: Range,
+    /// Ids within the window not covered by any range
+    gaps: RangeSet,
+    /// Number of Ids within the window covered by ranges
+    covered: u128,
+    /// Input ranges fully contained in other input ranges
+    redundant: Vec<&'a Source>,
+}
+
+impl Coverage<'_> {
+    /// Returns the largest uncovered range, the first one on a tie.
+    pub fn largest_gap(&self) -> Option<&Range> {
+        self.gaps
+            .ranges()
+            .iter()
+            .rev()
+            .max_by_key(|gap| gap.count())
+    }
+
+    /// Returns the share of covered Ids within the window in percent.
+    pub fn percentage(&self) -> f64 {
+        100.0 * self.covered as f64 / self.window.count() as f64
+    }
+}
+
+/// Keeps the original input ranges to explain the classification of ingredients.
+#[derive(Debug, Clone)]
+struct Inventory {
+    /// Sources sorted by the start of their range
+    sources: Vec<Source>,
+}
+
+impl Inventory {
+    pub fn new(mut sources: Vec<Source>) -> Self {
+        sources.sort_by_key(|source| (source.range.min, source.line));
+        Self { sources }
+    }
+
+    /// Returns the range from the lowest start to the highest end of all sources.
+    pub fn bounds(&self) -> Option<Range> {
+        let min = self.sources.first()?.range.min;
+        let max = self.sources.iter().map(|source| source.range.max).max()?;
+        Some(Range::new(min, max))
+    }
+
+    /// Returns all sources fully contained in another source.
+    ///
+    /// Of multiple identical ranges only the one from the earliest line is kept.
+    pub fn redundant(&self) -> Vec<&Source> {
+        let mut sources = self.sources.iter().collect::<Vec<_>>();
+        sources.sort_by_key(|source| {
+            (
+                source.range.min,
+                std::cmp::Reverse(source.range.max),
+                source.line,
+            )
+        });
+
+        // all previous sources start at or before the current one, it is contained if the one
+        // ending last contains it
+        let mut widest: Option<&Source> = None;
+        let mut redundant = Vec::new();
+        for source in sources {
+            match widest {
+                Some(widest) if widest.range.contains_range(&source.range) => {
+                    redundant.push(source)
+                }
+                _ => widest = Some(source),
+            }
+        }
+        redundant.sort_by_key(|source| source.line);
+        redundant
+    }
+
+    /// Analyzes how well the merged ranges cover the given window of Ids.
+    pub fn coverage(&self, window: Range) -> Coverage<'_> {
+        let fresh = self
+            .sources
+            .iter()
+            .map(|source| source.range.clone())
+            .collect::<RangeSet>();
+        let window_set = RangeSet::from_iter([window.clone()]);
+
+        Coverage {
+            gaps: window_set.difference(&fresh),
+            covered: window_set.intersection(&fresh).count(),
+            redundant: self.redundant(),
+            window,
+        }
+    }
+
+    /// Explains the classification of the ingredient.
+    ///
+    /// A fresh ingredient lists all ranges containing it, ordered by their start. For a spoiled
+    /// ingredient the range ending closest below & the range starting closest above are returned,
+    /// on a tie the range from the earlier line wins.
+    pub fn explain(&self, id: Id) -> Explanation<'_> {
+        // only ranges starting at or before the Id can contain it
+        let end = self
+            .sources
+            .partition_point(|source| source.range.min <= id);
+        let candidates = &self.sources[..end];
+
+        let covering = candidates
+            .iter()
+            .filter(|source| source.range.contains(id))
+            .collect::<Vec<_>>();
+        if !covering.is_empty() {
+            return Explanation::Fresh(covering);
+        }
+
+        let below = candidates
+            .iter()
+            .min_by_key(|source| (id - source.range.max, source.line));
+        // sources are sorted by start & line, the next one is the nearest above
+        let above = self.sources.get(end);
+        Explanation::Spoiled { below, above }
+    }
+}
+
+/// Parses ranges with their line numbers and ingredients
+fn parse_sources(input: &str) -> (Vec<Source>, Vec<Id>) {
     let (ranges, ingredients) = input.split_once("\n\n").expect("Failed to parse input");
 
-    let mut ranges = ranges
+    let sources = ranges
         .lines()
-        .filter_map(|line| line.trim().split_once('-'))
-        .map(|(l, r)| Range::new(l.parse::<Id>().unwrap(), r.parse::<Id>().unwrap()))
+        .enumerate()
+        .filter_map(|(index, line)| Some((index + 1, line.trim().split_once('-')?)))
+        .map(|(line, (l, r))| Source {
+            line,
+            range: Range::new(l.parse::<Id>().unwrap(), r.parse::<Id>().unwrap()),
+        })
         .collect::<Vec<_>>();
 
-    // ranges can overlap
-    ranges.sort_by(|range, other| range.min.cmp(&other.min));
-
     let ingredients = ingredients
         .lines()
         .map(|line| line.trim().parse::<Id>().unwrap())
         .collect::<Vec<_>>();
 
+    (sources, ingredients)
+}
+
+/// Parses ranges and ingredients
+///
+/// NOTE: ranges can consist of a single element, e.g. 11-11
+fn parse_input(input: &str) -> (Vec<Range>, Vec<Id>) {
+    let (sources, ingredients) = parse_sources(input);
+    let mut ranges = sources
+        .into_iter()
+        .map(|source| source.range)
+        .collect::<Vec<_>>();
+
+    // ranges can overlap
+    ranges.sort_by_key(|range| range.min);
+
     (ranges, ingredients)
 }
 
-fn process_part1((ranges, ingredients): &(Vec<Range>, Vec<Id>)) -> usize {
-    ingredients
-        .iter()
-        .filter(|id| ranges.iter().find(|range| range.contains(**id)).is_some())
-        .count()
+type Time = u64;
+
+/// What happens at a point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Action {
+    /// A range becomes fresh
+    Add(Range),
+    /// A previously added range expires
+    Remove(Range),
+    /// Checks if an ingredient is fresh
+    Check(Id),
 }
 
-fn process_part2((ranges, _): &(Vec<Range>, Vec<Id>)) -> usize {
-    let mut combined: Vec<Range> = Vec::new();
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Event {
+    time: Time,
+    /// Line number in the input, starting at 1
+    line: usize,
+    action: Action,
+}
 
-    // compare with all existing ranges
-    for range in ranges {
-        if let Some(combined) = combined
-            .iter_mut()
-            .find(|other| other.overlaps(range))
-        {
-            combined.merge(range);
-        } else {
-            combined.push(range.clone());
+/// The outcome of a [`Action::Check`] event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Check {
+    time: Time,
+    id: Id,
+    fresh: bool,
+}
+
+/// The set of fresh ranges at a point in time.
+///
+/// Ranges may overlap, an Id stays fresh as long as any added range containing it is active.
+#[derive(Debug, Default)]
+struct Timeline {
+    /// Active ranges with the number of times they were added
+    active: HashMap<Range, usize>,
+    /// Merged active ranges, rebuilt on the next check after a change
+    fresh: Option<RangeSet>,
+}
+
+impl Timeline {
+    pub fn add(&mut self, range: Range) {
+        *self.active.entry(range).or_default() += 1;
+        self.fresh = None;
+    }
+
+    pub fn remove(&mut self, range: &Range) -> anyhow::Result<()> {
+        let count = self
+            .active
+            .get_mut(range)
+            .ok_or_else(|| anyhow!("Range {}-{} is not active", range.min, range.max))?;
+        *count -= 1;
+        if *count == 0 {
+            self.active.remove(range);
+        }
+        self.fresh = None;
+        Ok(())
+    }
+
+    pub fn is_fresh(&mut self, id: Id) -> bool {
+        self.fresh
+            .get_or_insert_with(|| self.active.keys().cloned().collect())
+            .contains(id)
+    }
+}
+
+/// Parses timestamped events, one per line, e.g.
+///
+/// ```text
+/// 1 add 3-5
+/// 2 check 4
+/// 3 remove 3-5
+/// ```
+fn parse_events(input: &str) -> anyhow::Result<Vec<Event>> {
+    input
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(line, text)| parse_event(line, text).with_context(|| format!("Line {}", line)))
+        .collect::<anyhow::Result<Vec<_>>>()
+}
+
+fn parse_event(line: usize, text: &str) -> anyhow::Result<Event> {
+    let parse_range = |range: &str| -> anyhow::Result<Range> {
+        let (min, max) = range.split_once('-').context("Range without '-'")?;
+        let (min, max) = (min.parse::<Id>()?, max.parse::<Id>()?);
+        if min > max {
+            bail!("Range {}-{} is reversed", min, max);
+        }
+        Ok(Range::new(min, max))
+    };
+
+    let parts = text.split_whitespace().collect::<Vec<_>>();
+    let [time, action, value] = parts[..] else {
+        bail!("Expected '<time> <action> <value>'");
+    };
+
+    let action = match action {
+        "add" => Action::Add(parse_range(value)?),
+        "remove" => Action::Remove(parse_range(value)?),
+        "check" => Action::Check(value.parse::<Id>()?),
+        _ => bail!("Unknown action '{}'", action),
+    };
+    let time = time.parse::<Time>()?;
+    Ok(Event { time, line, action })
+}
+
+/// Applies the events in order of their time, returns the outcome of all checks.
+///
+/// Events at the same time are applied in input order.
+fn replay(events: &[Event]) -> anyhow::Result<Vec<Check>> {
+    let mut events = events.iter().collect::<Vec<_>>();
+    events.sort_by_key(|event| event.time);
+
+    let mut timeline = Timeline::default();
+    let mut checks = Vec::new();
+    for event in events {
+        match &event.action {
+            Action::Add(range) => timeline.add(range.clone()),
+            Action::Remove(range) => timeline
+                .remove(range)
+                .with_context(|| format!("Line {}", event.line))?,
+            Action::Check(id) => checks.push(Check {
+                time: event.time,
+                id: *id,
+                fresh: timeline.is_fresh(*id),
+            }),
         }
     }
+    Ok(checks)
+}
 
-    combined.iter().map(|range| range.count()).sum::<u64>() as usize
+fn process_part1((ranges, ingredients): &(Vec<Range>, Vec<Id>)) -> usize {
+    let fresh = ranges.iter().cloned().collect::<RangeSet>();
+    let mut ingredients = ingredients.clone();
+    ingredients.sort_unstable();
+    fresh
+        .contains_sorted(ingredients)
+        .filter(|is_fresh| *is_fresh)
+        .count()
+}
+
+fn process_part2((ranges, _): &(Vec<Range>, Vec<Id>)) -> u128 {
+    let fresh = ranges.iter().cloned().collect::<RangeSet>();
+    fresh.count()
 }
 
 fn main() {
@@ -119,10 +597,214 @@ mod tests {
     #[test]
     fn test_part2() {
         assert_eq!(14, process_part2(&parse_input(INPUT)));
+
+        let input = parse_input("0-18446744073709551615\n\n1");
+        assert_eq!(1 << 64, process_part2(&input));
     }
 
     #[test]
     fn test_range_merge() {
         assert!(Range::new(1, 4).overlaps(&Range::new(4, 7)));
+        assert!(Range::new(4, 7).overlaps(&Range::new(1, 10)));
+        assert!(!Range::new(3, 5).overlaps(&Range::new(6, 8)));
+        assert!(Range::new(3, 5).touches(&Range::new(6, 8)));
+    }
+
+    fn set(ranges: &[(Id, Id)]) -> RangeSet {
+        ranges
+            .iter()
+            .map(|&(min, max)| Range::new(min, max))
+            .collect()
+    }
+
+    #[test]
+    fn test_range_set_insert() {
+        assert_eq!(set(&[(3, 8)]), set(&[(3, 5), (6, 8)]));
+        assert_eq!(set(&[(1, 10)]), set(&[(4, 7), (1, 10)]));
+        assert_eq!(
+            set(&[(1, 2), (4, 12)]),
+            set(&[(10, 12), (1, 2), (4, 5), (6, 9)])
+        );
+        assert_eq!(set(&[(0, Id::MAX)]), set(&[(5, Id::MAX), (0, 4)]));
+        assert_eq!(1 << 64, set(&[(5, Id::MAX), (0, 4)]).count());
+        assert_eq!(14, set(&[(3, 5), (10, 14), (16, 20), (12, 18)]).count());
+    }
+
+    #[test]
+    fn test_range_set_remove() {
+        let mut ranges = set(&[(1, 10), (20, 30)]);
+        ranges.remove(&Range::new(5, 22));
+        assert_eq!(set(&[(1, 4), (23, 30)]), ranges);
+        ranges.remove(&Range::new(2, 2));
+        assert_eq!(set(&[(1, 1), (3, 4), (23, 30)]), ranges);
+        ranges.remove(&Range::new(0, 100));
+        assert!(ranges.is_empty());
+    }
+
+    #[test]
+    fn test_range_set_contains() {
+        let ranges = set(&[(3, 5), (10, 14), (16, 20), (12, 18)]);
+        let ids = [1, 3, 5, 6, 8, 10, 15, 20, 21, 32];
+        let expected = [
+            false, true, true, false, false, true, true, true, false, false,
+        ];
+        assert_eq!(
+            expected.to_vec(),
+            ids.map(|id| ranges.contains(id)).to_vec()
+        );
+        assert_eq!(
+            expected.to_vec(),
+            ranges.contains_sorted(ids).collect::<Vec<_>>()
+        );
+        assert!(!RangeSet::new().contains(0));
+    }
+
+    #[test]
+    fn test_range_set_many_queries() {
+        let mut seed = 0x5eed_u64;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        let ranges = (0..100_000)
+            .map(|_| {
+                let min = random() % 1_000_000_000_000;
+                Range::new(min, min + random() % 5_000_000)
+            })
+            .collect::<RangeSet>();
+        let mut ids = (0..1_000_000)
+            .map(|_| random() % 1_000_000_000_000)
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+
+        let expected = ids.iter().filter(|id| ranges.contains(**id)).count();
+        let count = ranges.contains_sorted(ids).filter(|fresh| *fresh).count();
+        assert_eq!(expected, count);
+    }
+
+    #[test]
+    fn test_explain() {
+        let (sources, _) = parse_sources(INPUT);
+        let inventory = Inventory::new(sources);
+
+        let lines = |explanation: Explanation| match explanation {
+            Explanation::Fresh(sources) => (sources.iter().map(|s| s.line).collect(), None, None),
+            Explanation::Spoiled { below, above } => {
+                (vec![], below.map(|s| s.line), above.map(|s| s.line))
+            }
+        };
+
+        assert_eq!((vec![], None, Some(1)), lines(inventory.explain(1)));
+        assert_eq!((vec![1], None, None), lines(inventory.explain(5)));
+        assert_eq!((vec![], Some(1), Some(2)), lines(inventory.explain(8)));
+        assert_eq!((vec![2], None, None), lines(inventory.explain(11)));
+        assert_eq!((vec![4, 3], None, None), lines(inventory.explain(17)));
+        assert_eq!((vec![], Some(3), None), lines(inventory.explain(32)));
+
+        assert_eq!(
+            "fresh, contained in [line 4: 12-18], [line 3: 16-20]",
+            inventory.explain(17).to_string()
+        );
+        assert_eq!(
+            "spoiled, nearest below [line 3: 16-20], nearest above [none]",
+            inventory.explain(32).to_string()
+        );
+    }
+
+    #[test]
+    fn test_coverage() {
+        let (sources, _) = parse_sources(INPUT);
+        let inventory = Inventory::new(sources);
+        assert_eq!(Some(Range::new(3, 20)), inventory.bounds());
+
+        let coverage = inventory.coverage(inventory.bounds().unwrap());
+        assert_eq!(set(&[(6, 9)]), coverage.gaps);
+        assert_eq!(14, coverage.covered);
+        assert!((coverage.percentage() - 77.777).abs() < 0.001);
+        assert!(coverage.redundant.is_empty());
+
+        let coverage = inventory.coverage(Range::new(0, 25));
+        assert_eq!(set(&[(0, 2), (6, 9), (21, 25)]), coverage.gaps);
+        assert_eq!(Some(&Range::new(21, 25)), coverage.largest_gap());
+
+        let coverage = inventory.coverage(Range::new(10, 20));
+        assert_eq!(None, coverage.largest_gap());
+        assert_eq!(100.0, coverage.percentage());
+    }
+
+    #[test]
+    fn test_redundant_ranges() {
+        let (sources, _) = parse_sources("1-10\n2-3\n5-10\n1-10\n9-12\n\n1");
+        let inventory = Inventory::new(sources);
+        let lines = inventory
+            .redundant()
+            .iter()
+            .map(|source| source.line)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![2, 3, 4], lines);
+    }
+
+    #[test]
+    fn test_replay_events() {
+        let input = r#"
+            1 add 3-5
+            2 add 4-8
+            3 check 7
+            5 remove 4-8
+            5 check 7
+            4 check 4
+            6 check 4
+            7 remove 3-5
+            8 check 4
+        "#;
+        let events = parse_events(input).unwrap();
+        assert_eq!(9, events.len());
+        assert_eq!(Action::Add(Range::new(3, 5)), events[0].action);
+        assert_eq!(2, events[0].line);
+
+        let checks = replay(&events)
+            .unwrap()
+            .into_iter()
+            .map(|check| (check.time, check.id, check.fresh))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (3, 7, true),
+                (4, 4, true),
+                (5, 7, false),
+                (6, 4, true),
+                (8, 4, false)
+            ],
+            checks
+        );
+    }
+
+    #[test]
+    fn test_replay_errors() {
+        assert!(parse_events("1 add 5-3").is_err());
+        assert!(parse_events("1 expire 3-5").is_err());
+        assert!(parse_events("1 check").is_err());
+        let error = parse_events("1 add 3-5\n\n2 expire 3-5").unwrap_err();
+        assert_eq!("Line 3: Unknown action 'expire'", format!("{:#}", error));
+
+        let events = parse_events("1 add 3-5\n2 remove 3-6").unwrap();
+        let error = replay(&events).unwrap_err();
+        assert_eq!("Line 2: Range 3-6 is not active", format!("{:#}", error));
+    }
+
+    #[test]
+    fn test_range_set_operations() {
+        let left = set(&[(1, 5), (10, 20)]);
+        let right = set(&[(4, 12), (18, 25)]);
+        assert_eq!(set(&[(1, 25)]), left.union(&right));
+        assert_eq!(
+            set(&[(4, 5), (10, 12), (18, 20)]),
+            left.intersection(&right)
+        );
+        assert_eq!(set(&[(1, 3), (13, 17)]), left.difference(&right));
+        assert_eq!(set(&[(6, 9), (21, 25)]), right.difference(&left));
     }
 }
@@ -1,14 +1,102 @@
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    iter::Sum,
+    ops::Add,
+    str::FromStr,
+};
+
+use anyhow::{Context, anyhow, bail};
+use num_bigint::BigUint;
+
 const SPACE: u8 = b' ';
+/// Tabs are expanded to the next multiple of `TAB_WIDTH` columns
+const TAB_WIDTH: usize = 8;
 
 #[derive(Debug)]
 struct Equation {
-    numbers: Vec<u64>,
+    numbers: Vec<Number>,
     op: u8,
+    /// Column of the operator in the worksheet
+    column: usize,
+}
+
+/// The lines of a block numbers are read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Axis {
+    /// Rows from top to bottom, digits from left to right
+    Rows,
+    /// Columns from left to right, digits from top to bottom
+    Columns,
+    /// Diagonals going down to the right, from the bottom left to the top right corner, digits
+    /// from top to bottom
+    Diagonals,
+    /// Diagonals going down to the left, from the top left to the bottom right corner, digits
+    /// from top to bottom
+    AntiDiagonals,
+}
+
+/// Selects how numbers are read from a block, e.g. `columns,reverse-order`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Reading {
+    axis: Axis,
+    /// Reverses the order of the numbers
+    reverse_order: bool,
+    /// Reverses the order of the digits within each number
+    reverse_digits: bool,
+}
+
+impl Reading {
+    pub const ROWS: Reading = Reading::new(Axis::Rows);
+    pub const COLUMNS: Reading = Reading::new(Axis::Columns);
+
+    pub const fn new(axis: Axis) -> Self {
+        Self {
+            axis,
+            reverse_order: false,
+            reverse_digits: false,
+        }
+    }
+
+    pub const fn reverse_order(mut self) -> Self {
+        self.reverse_order = !self.reverse_order;
+        self
+    }
+
+    pub const fn reverse_digits(mut self) -> Self {
+        self.reverse_digits = !self.reverse_digits;
+        self
+    }
+}
+
+impl FromStr for Reading {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(',').map(str::trim);
+        let axis = match parts.next() {
+            Some("rows") => Axis::Rows,
+            Some("columns") => Axis::Columns,
+            Some("diagonals") => Axis::Diagonals,
+            Some("anti-diagonals") => Axis::AntiDiagonals,
+            axis => bail!("Unknown axis {:?}", axis),
+        };
+
+        parts.try_fold(Reading::new(axis), |reading, part| match part {
+            "reverse-order" => Ok(reading.reverse_order()),
+            "reverse-digits" => Ok(reading.reverse_digits()),
+            _ => bail!("Unknown reading option '{}'", part),
+        })
+    }
 }
 
 #[derive(Debug)]
 struct Block {
     pub matrix: Vec<Vec<u8>>,
+    /// Column of the first character of the block in the worksheet
+    pub column: usize,
 }
 
 impl Block {
@@ -20,29 +108,77 @@ impl Block {
             .cloned()
     }
 
+    /// Returns the column of the operator in the worksheet
+    pub fn op_column(&self) -> Option<usize> {
+        let offset = self.matrix[self.num_rows()]
+            .iter()
+            .position(|byte| *byte != SPACE)?;
+        Some(self.column + offset)
+    }
+
     /// Parse all values from rows and returns them.
-    pub fn row_values(&self) -> anyhow::Result<Vec<u64>> {
-        (0..self.num_rows())
-            .map(|row| self.row(row))
-            .collect::<anyhow::Result<Vec<_>>>()
+    pub fn row_values(&self) -> anyhow::Result<Vec<Number>> {
+        self.values(Reading::ROWS)
     }
 
     /// Parse all values from columns and returns them.
-    pub fn col_values(&self) -> anyhow::Result<Vec<u64>> {
-        (0..self.num_columns())
-            .map(|col| self.col(col))
-            .collect::<anyhow::Result<Vec<_>>>()
+    pub fn col_values(&self) -> anyhow::Result<Vec<Number>> {
+        self.values(Reading::COLUMNS)
     }
 
-    fn row(&self, row: usize) -> anyhow::Result<u64> {
-        Ok(str::from_utf8(&self.matrix[row])?.trim().parse::<u64>()?)
+    /// Parse all values along the lines of the reading, lines without digits are skipped.
+    pub fn values(&self, reading: Reading) -> anyhow::Result<Vec<Number>> {
+        let mut lines = self.lines(reading.axis);
+        if reading.reverse_order {
+            lines.reverse();
+        }
+
+        lines
+            .into_iter()
+            .map(|line| {
+                let mut value = line
+                    .iter()
+                    .map(|&(row, col)| self.matrix[row][col])
+                    .collect::<Vec<_>>();
+                if reading.reverse_digits {
+                    value.reverse();
+                }
+                value
+            })
+            .filter(|value| value.iter().any(|byte| *byte != SPACE))
+            .map(|value| str::from_utf8(&value)?.trim().parse::<Number>())
+            .collect::<anyhow::Result<Vec<_>>>()
     }
 
-    fn col(&self, column: usize) -> anyhow::Result<u64> {
-        let value = (0..self.num_rows())
-            .map(|row| self.matrix[row][column])
-            .collect::<Vec<_>>();
-        Ok(str::from_utf8(&value)?.trim().parse::<u64>()?)
+    /// Returns the `(row, column)` positions of all lines along the axis, see [`Axis`].
+    fn lines(&self, axis: Axis) -> Vec<Vec<(usize, usize)>> {
+        let (rows, cols) = (self.num_rows(), self.num_columns());
+        match axis {
+            Axis::Rows => (0..rows)
+                .map(|row| (0..cols).map(|col| (row, col)).collect())
+                .collect(),
+            Axis::Columns => (0..cols)
+                .map(|col| (0..rows).map(|row| (row, col)).collect())
+                .collect(),
+            // all positions with `col - row == offset - (rows - 1)`
+            Axis::Diagonals => (0..rows + cols - 1)
+                .map(|offset| {
+                    (0..rows)
+                        .filter_map(|row| Some((row, (offset + row).checked_sub(rows - 1)?)))
+                        .filter(|(_, col)| *col < cols)
+                        .collect()
+                })
+                .collect(),
+            // all positions with `col + row == sum`
+            Axis::AntiDiagonals => (0..rows + cols - 1)
+                .map(|sum| {
+                    (0..rows)
+                        .filter_map(|row| Some((row, sum.checked_sub(row)?)))
+                        .filter(|(_, col)| *col < cols)
+                        .collect()
+                })
+                .collect(),
+        }
     }
 
     fn num_columns(&self) -> usize {
@@ -55,92 +191,437 @@ impl Block {
     }
 }
 
+/// A binary operator, returns `None` if the result is not defined, e.g. on overflow.
+type Fold = fn(u64, u64) -> Option<u64>;
+
+/// The same operator on big integers, returns `None` if the result is not defined.
+type BigFold = fn(&BigUint, &BigUint) -> Option<BigUint>;
+
+#[derive(Debug, Clone)]
+struct Operator {
+    name: &'static str,
+    fold: Fold,
+    big: BigFold,
+}
+
+/// A non-negative number, kept as `u64` as long as it fits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Number {
+    Small(u64),
+    Big(BigUint),
+}
+
+impl From<u64> for Number {
+    fn from(value: u64) -> Self {
+        Number::Small(value)
+    }
+}
+
+impl From<BigUint> for Number {
+    fn from(value: BigUint) -> Self {
+        match u64::try_from(&value) {
+            Ok(value) => Number::Small(value),
+            Err(_) => Number::Big(value),
+        }
+    }
+}
+
+impl FromStr for Number {
+    type Err = anyhow::Error;
+
+    /// Parses the digits as `u64`, numbers with more digits as big integer.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<u64>() {
+            Ok(value) => Ok(Number::Small(value)),
+            Err(_) => Ok(Number::from(s.parse::<BigUint>()?)),
+        }
+    }
+}
+
+impl PartialEq<u64> for Number {
+    fn eq(&self, other: &u64) -> bool {
+        *self == Number::Small(*other)
+    }
+}
+
+impl PartialEq<Number> for u64 {
+    fn eq(&self, other: &Number) -> bool {
+        other == self
+    }
+}
+
+impl Display for Number {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Number::Small(value) => write!(f, "{}", value),
+            Number::Big(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl Add for Number {
+    type Output = Number;
+
+    fn add(self, rhs: Number) -> Number {
+        if let (Number::Small(lhs), Number::Small(rhs)) = (&self, &rhs)
+            && let Some(result) = lhs.checked_add(*rhs)
+        {
+            return Number::Small(result);
+        }
+        Number::from(self.to_big() + rhs.to_big())
+    }
+}
+
+impl Sum for Number {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Number::Small(0), Add::add)
+    }
+}
+
+impl Number {
+    /// Applies the operator, uses checked `u64` arithmetic first & falls back to big integers.
+    pub fn apply(&self, operator: &Operator, rhs: &Number) -> Option<Number> {
+        if let (Number::Small(lhs), Number::Small(rhs)) = (self, rhs)
+            && let Some(result) = (operator.fold)(*lhs, *rhs)
+        {
+            return Some(Number::Small(result));
+        }
+        (operator.big)(&self.to_big(), &rhs.to_big()).map(Number::from)
+    }
+
+    fn to_big(&self) -> BigUint {
+        match self {
+            Number::Small(value) => BigUint::from(*value),
+            Number::Big(value) => value.clone(),
+        }
+    }
+}
+
+/// The registry of all known operators, by their symbol.
+#[derive(Debug, Clone)]
+struct Operators {
+    operators: HashMap<u8, Operator>,
+}
+
+impl Default for Operators {
+    /// Registers `+`, `*`, `-`, `/`, `<` (min), `>` (max) & `|` (concatenation).
+    fn default() -> Self {
+        let mut operators = Self {
+            operators: HashMap::new(),
+        };
+        operators.register(b'+', "add", u64::checked_add, |l, r| Some(l + r));
+        operators.register(b'*', "multiply", u64::checked_mul, |l, r| Some(l * r));
+        operators.register(b'-', "subtract", u64::checked_sub, |l, r| {
+            (l >= r).then(|| l - r)
+        });
+        operators.register(b'/', "divide", u64::checked_div, |l, r| {
+            (*r != BigUint::ZERO).then(|| l / r)
+        });
+        operators.register(
+            b'<',
+            "min",
+            |l, r| Some(l.min(r)),
+            |l, r| Some(l.min(r).clone()),
+        );
+        operators.register(
+            b'>',
+            "max",
+            |l, r| Some(l.max(r)),
+            |l, r| Some(l.max(r).clone()),
+        );
+        operators.register(b'|', "concat", concat, |l, r| {
+            Some(l * BigUint::from(10u8).pow(r.to_string().len() as u32) + r)
+        });
+        operators
+    }
+}
+
+impl Operators {
+    /// Adds a new operator, replaces an existing one with the same symbol.
+    ///
+    /// `big` is used when `fold` fails, it needs to return the same results for all numbers `fold`
+    /// supports.
+    pub fn register(&mut self, symbol: u8, name: &'static str, fold: Fold, big: BigFold) {
+        let operator = Operator { name, fold, big };
+        self.operators.insert(symbol, operator);
+    }
+
+    pub fn get(&self, symbol: u8) -> Option<&Operator> {
+        self.operators.get(&symbol)
+    }
+}
+
+/// Appends the digits of `rhs` to `lhs`, e.g. 12 | 34 = 1234
+fn concat(lhs: u64, rhs: u64) -> Option<u64> {
+    let shift = 10u64.checked_pow(rhs.checked_ilog10().unwrap_or(0) + 1)?;
+    lhs.checked_mul(shift)?.checked_add(rhs)
+}
+
 impl Equation {
-    pub fn calculate_total(&self) -> u64 {
-        match self.op {
-            b'*' => self.numbers.iter().product(),
-            b'+' => self.numbers.iter().sum(),
-            _ => panic!("what??"),
+    /// Folds all numbers from left to right with the operator, e.g. `a - b - c` is `(a - b) - c`.
+    ///
+    /// Results exceeding `u64` are calculated exactly with big integers. Fails for an unknown
+    /// operator, an empty equation or when a step is not defined, for example a negative result
+    /// or a division by zero.
+    pub fn calculate_total(&self, operators: &Operators) -> anyhow::Result<Number> {
+        let symbol = char::from(self.op);
+        let Some(operator) = operators.get(self.op) else {
+            bail!("Unknown operator '{}' at column {}", symbol, self.column);
+        };
+
+        let (first, rest) = self
+            .numbers
+            .split_first()
+            .ok_or_else(|| anyhow!("No numbers for '{}' at column {}", symbol, self.column))?;
+        rest.iter().try_fold(first.clone(), |total, number| {
+            total.apply(operator, number).ok_or_else(|| {
+                anyhow!(
+                    "Failed to {} {} and {} at column {}",
+                    operator.name,
+                    total,
+                    number,
+                    self.column
+                )
+            })
+        })
+    }
+}
+
+impl Display for Equation {
+    /// Writes the numbers joined by the operator, e.g. `123 * 45 * 6`
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (index, number) in self.numbers.iter().enumerate() {
+            if index > 0 {
+                write!(f, " {} ", char::from(self.op))?;
+            }
+            write!(f, "{}", number)?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders the parsed worksheet, with the equations of each block annotated underneath.
+///
+/// Each block is underlined to show its boundaries, followed by the equations for the row &
+/// column interpretation of all blocks.
+struct Worksheet<'a> {
+    blocks: &'a [Block],
+    operators: &'a Operators,
+}
+
+impl<'a> Worksheet<'a> {
+    pub fn new(blocks: &'a [Block], operators: &'a Operators) -> Self {
+        Self { blocks, operators }
+    }
+
+    /// Writes a single interpretation of a block, with its result or the error.
+    fn write_equation(
+        &self,
+        f: &mut Formatter<'_>,
+        label: &str,
+        block: &Block,
+        reading: Reading,
+    ) -> std::fmt::Result {
+        let numbers = match block.values(reading) {
+            Ok(numbers) => numbers,
+            Err(error) => return writeln!(f, "    {:<8} error: {}", label, error),
+        };
+        let equation = Equation {
+            numbers,
+            op: block.op().unwrap_or(SPACE),
+            column: block.op_column().unwrap_or(block.column),
+        };
+        match equation.calculate_total(self.operators) {
+            Ok(result) => writeln!(f, "    {:<8} {} = {}", label, equation, result),
+            Err(error) => writeln!(f, "    {:<8} {} = error: {}", label, equation, error),
         }
     }
 }
 
-fn process_part1(blocks: &[Block]) -> u64 {
+impl Display for Worksheet<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let width = self
+            .blocks
+            .iter()
+            .map(|block| block.column + block.num_columns())
+            .max()
+            .unwrap_or(0);
+        let height = self.blocks.first().map_or(0, |block| block.matrix.len());
+
+        let mut lines = vec![vec![SPACE; width]; height + 1];
+        for block in self.blocks {
+            let columns = block.column..block.column + block.num_columns();
+            for (row, values) in block.matrix.iter().enumerate() {
+                lines[row][columns.clone()].copy_from_slice(values);
+            }
+            lines[height][columns].fill(b'-');
+        }
+        for line in lines {
+            writeln!(f, "{}", String::from_utf8_lossy(&line).trim_end())?;
+        }
+
+        for (index, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "block {} at column {}", index + 1, block.column)?;
+            self.write_equation(f, "rows:", block, Reading::ROWS)?;
+            self.write_equation(f, "columns:", block, Reading::COLUMNS)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sums up the results of all equations.
+fn calculate_sum(equations: &[Equation], operators: &Operators) -> anyhow::Result<Number> {
+    equations
+        .iter()
+        .map(|equation| equation.calculate_total(operators))
+        .sum()
+}
+
+fn process_part1(blocks: &[Block]) -> Number {
     let equations = convert_part1(blocks);
-    equations.iter().map(Equation::calculate_total).sum::<u64>()
+    calculate_sum(&equations, &Operators::default()).expect("Failed to calculate")
 }
 
-/// Ignore error handling
-fn parse_blocks(input: &str) -> Vec<Block> {
+/// Expands tabs and removes trailing carriage returns.
+fn normalize_line(line: &str) -> Vec<u8> {
     let mut result = Vec::new();
-    let lines = input
+    for byte in line.trim_end_matches('\r').bytes() {
+        if byte == b'\t' {
+            let width = TAB_WIDTH - result.len() % TAB_WIDTH;
+            result.extend(std::iter::repeat_n(SPACE, width));
+        } else {
+            result.push(byte);
+        }
+    }
+    result
+}
+
+/// Splits the worksheet into blocks separated by columns of spaces.
+///
+/// Lines may have different lengths, e.g. when trailing spaces were stripped, shorter lines are
+/// padded with spaces. Tabs are expanded & multiple separator columns are treated as one.
+fn parse_blocks(input: &str) -> anyhow::Result<Vec<Block>> {
+    let mut lines = input
         .lines()
-        .filter(|line| !line.is_empty())
-        .map(|line| line.as_bytes())
+        .map(normalize_line)
+        .filter(|line| line.iter().any(|byte| *byte != SPACE))
         .collect::<Vec<_>>();
-    let rows = lines.len();
 
+    if lines.len() < 2 {
+        bail!("Expected rows of numbers followed by an operator row");
+    }
+    if lines[lines.len() - 1].iter().any(u8::is_ascii_digit) {
+        bail!("Missing operator row, the last line contains digits");
+    }
+
+    let width = lines.iter().map(Vec::len).max().unwrap_or(0);
+    for line in lines.iter_mut() {
+        line.resize(width, SPACE);
+    }
+
+    let is_separator = |col: usize| lines.iter().all(|line| line[col] == SPACE);
+
+    let mut result = Vec::new();
     let mut col = 0;
-    for new_col in 0..lines[0].len() {
-        let new_col = if (0..rows).all(|row| lines[row][new_col] == SPACE) {
-            new_col
-        } else if new_col == lines[0].len() - 1 {
-            lines[0].len()
-        } else {
+    while col < width {
+        if is_separator(col) {
+            col += 1;
             continue;
-        };
+        }
 
-        let matrix = (0..rows)
-            .map(|row| lines[row][col..new_col].to_vec())
-            .collect::<Vec<_>>();
+        let start = col;
+        while col < width && !is_separator(col) {
+            col += 1;
+        }
 
-        result.push(Block { matrix });
-        col = new_col + 1;
+        let matrix = lines
+            .iter()
+            .map(|line| line[start..col].to_vec())
+            .collect::<Vec<_>>();
+        let block = Block {
+            matrix,
+            column: start,
+        };
+        if block.op().is_none() {
+            bail!("Missing operator for block at column {}", start);
+        }
+        result.push(block);
     }
 
-    result
+    Ok(result)
 }
 
-fn convert_part1(blocks: &[Block]) -> Vec<Equation> {
+/// Converts all blocks into equations, reading numbers as selected.
+fn convert(blocks: &[Block], reading: Reading) -> anyhow::Result<Vec<Equation>> {
     let mut equations = Vec::new();
 
     for block in blocks {
-        let numbers = block.row_values().expect("Failed to parse values");
+        let numbers = block
+            .values(reading)
+            .with_context(|| format!("Failed to read block at column {}", block.column))?;
         let op = block.op().unwrap();
-        equations.push(Equation { numbers, op });
+        let column = block.op_column().unwrap();
+        equations.push(Equation {
+            numbers,
+            op,
+            column,
+        });
     }
 
-    equations
+    Ok(equations)
 }
 
-fn convert_part2(blocks: &[Block]) -> Vec<Equation> {
-    let mut equations = Vec::new();
+fn convert_part1(blocks: &[Block]) -> Vec<Equation> {
+    convert(blocks, Reading::ROWS).expect("Failed to parse values")
+}
 
-    for block in blocks {
-        let numbers = block.col_values().expect("Failed to parse values");
-        let op = block.op().unwrap();
-        equations.push(Equation { numbers, op });
-    }
+fn convert_part2(blocks: &[Block]) -> Vec<Equation> {
+    convert(blocks, Reading::COLUMNS).expect("Failed to parse values")
+}
 
-    equations
+/// Calculates the sum of all equations, reading numbers as selected.
+fn process(blocks: &[Block], reading: Reading, operators: &Operators) -> anyhow::Result<Number> {
+    calculate_sum(&convert(blocks, reading)?, operators)
 }
 
-fn process_part2(blocks: &[Block]) -> u64 {
+fn process_part2(blocks: &[Block]) -> Number {
     let equations = convert_part2(blocks);
-    equations.iter().map(Equation::calculate_total).sum::<u64>()
+    calculate_sum(&equations, &Operators::default()).expect("Failed to calculate")
 }
 
 fn main() {
-    let blocks = parse_blocks(include_str!("input.txt"));
+    let blocks = parse_blocks(include_str!("input.txt")).expect("Failed to parse worksheet");
     let result = process_part1(&blocks);
     println!("PART 1: {}", result);
     let result = process_part2(&blocks);
     println!("PART 2: {}", result);
+
+    // either `--render` or an optional reading, e.g. `columns,reverse-order,reverse-digits`
+    match std::env::args().nth(1) {
+        Some(arg) if arg == "--render" => {
+            print!("{}", Worksheet::new(&blocks, &Operators::default()));
+        }
+        Some(reading) => {
+            let result = reading
+                .parse::<Reading>()
+                .and_then(|reading| process(&blocks, reading, &Operators::default()));
+            match result {
+                Ok(result) => println!("{}: {}", reading, result),
+                Err(error) => eprintln!("{}: {:#}", reading, error),
+            }
+        }
+        None => (),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{parse_blocks, process_part1, process_part2};
+    use num_bigint::BigUint;
+
+    use crate::{
+        Axis, Equation, Number, Operators, Reading, Worksheet, parse_blocks, process,
+        process_part1, process_part2,
+    };
 
     const INPUT: &str = r#"
 123 328  51 64 
@@ -150,20 +631,20 @@ mod tests {
 
     #[test]
     fn test_part1() {
-        let blocks = parse_blocks(INPUT);
+        let blocks = parse_blocks(INPUT).unwrap();
         assert_eq!(4277556, process_part1(&blocks));
     }
 
     #[test]
     fn test_part2() {
-        let blocks = parse_blocks(INPUT);
+        let blocks = parse_blocks(INPUT).unwrap();
         let result = process_part2(&blocks);
         assert_eq!(3263827, result);
     }
 
     #[test]
     fn test_parse_blocks() {
-        let blocks = parse_blocks(INPUT);
+        let blocks = parse_blocks(INPUT).unwrap();
         assert_eq!(4, blocks.len());
 
         let block = &blocks[0];
@@ -173,4 +654,198 @@ mod tests {
         let cols = block.col_values().unwrap();
         assert_eq!(vec![1, 24, 356], cols);
     }
+
+    fn calculate(numbers: &[u64], op: u8) -> anyhow::Result<Number> {
+        let equation = Equation {
+            numbers: numbers.iter().copied().map(Number::from).collect(),
+            op,
+            column: 4,
+        };
+        equation.calculate_total(&Operators::default())
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(17, calculate(&[10, 4, 3], b'+').unwrap());
+        assert_eq!(120, calculate(&[10, 4, 3], b'*').unwrap());
+        assert_eq!(3, calculate(&[10, 4, 3], b'-').unwrap());
+        assert_eq!(2, calculate(&[100, 7, 5], b'/').unwrap());
+        assert_eq!(3, calculate(&[10, 4, 3], b'<').unwrap());
+        assert_eq!(10, calculate(&[10, 4, 3], b'>').unwrap());
+        assert_eq!(10403, calculate(&[10, 40, 3], b'|').unwrap());
+        assert_eq!(100, calculate(&[10, 0], b'|').unwrap());
+        assert_eq!(7, calculate(&[7], b'-').unwrap());
+    }
+
+    #[test]
+    fn test_operator_errors() {
+        let error = calculate(&[1, 2], b'%').unwrap_err();
+        assert_eq!("Unknown operator '%' at column 4", error.to_string());
+        assert!(calculate(&[], b'+').is_err());
+        assert!(calculate(&[3, 4], b'-').is_err());
+        assert!(calculate(&[3, 0], b'/').is_err());
+    }
+
+    #[test]
+    fn test_big_numbers() {
+        let max = BigUint::from(u64::MAX);
+        let result = calculate(&[u64::MAX, 2], b'*').unwrap();
+        assert_eq!(Number::Big(&max * 2u8), result);
+        assert_eq!("36893488147419103230", result.to_string());
+
+        // falls back to u64 once the result fits again
+        let result = calculate(&[u64::MAX, 10, 100], b'/');
+        assert_eq!(Number::Small(u64::MAX / 10 / 100), result.unwrap());
+        let result = calculate(&[u64::MAX, u64::MAX, u64::MAX], b'-');
+        assert!(result.is_err());
+        let result = calculate(&[u64::MAX, 1, u64::MAX], b'|').unwrap();
+        assert_eq!(format!("{}1{}", u64::MAX, u64::MAX), result.to_string());
+
+        // a tall block of large numbers
+        let numbers = vec![999_999_999_999u64; 40];
+        let expected = BigUint::from(999_999_999_999u64).pow(40);
+        assert_eq!(Number::Big(expected), calculate(&numbers, b'*').unwrap());
+
+        let sum = [Number::Small(u64::MAX), Number::Small(1)]
+            .into_iter()
+            .sum::<Number>();
+        assert_eq!(Number::Big(max + 1u8), sum);
+    }
+
+    #[test]
+    fn test_tall_worksheet_columns() {
+        // 22 rows, reading by columns gives numbers of 22 digits
+        let mut input = "91\n".repeat(22);
+        input.push_str("* \n");
+        let blocks = parse_blocks(&input).unwrap();
+
+        let nines = "9".repeat(22).parse::<BigUint>().unwrap();
+        let ones = "1".repeat(22).parse::<BigUint>().unwrap();
+        let expected = Number::Big(nines * ones);
+        let result = process(&blocks, Reading::COLUMNS, &Operators::default()).unwrap();
+        assert_eq!(expected, result);
+
+        let expected = Number::Big(BigUint::from(91u8).pow(22));
+        assert_eq!(expected, process_part1(&blocks));
+        assert_eq!(result, process_part2(&blocks));
+    }
+
+    #[test]
+    fn test_register_operator() {
+        let mut operators = Operators::default();
+        operators.register(b'%', "modulo", u64::checked_rem, |l, r| {
+            (*r != BigUint::ZERO).then(|| l % r)
+        });
+        let equation = Equation {
+            numbers: vec![Number::from(100), Number::from(30), Number::from(4)],
+            op: b'%',
+            column: 0,
+        };
+        assert_eq!(2, equation.calculate_total(&operators).unwrap());
+    }
+
+    #[test]
+    fn test_unknown_operator_column() {
+        let blocks = parse_blocks("12 3\n 4 5\n+  %").unwrap();
+        assert_eq!(Some(3), blocks[1].op_column());
+        let equations = crate::convert_part1(&blocks);
+        let error = crate::calculate_sum(&equations, &Operators::default()).unwrap_err();
+        assert_eq!("Unknown operator '%' at column 3", error.to_string());
+    }
+
+    #[test]
+    fn test_parse_ragged_blocks() {
+        // trailing spaces stripped, tabs and multiple separator columns
+        let input = "123 328\t 51 64\n 45 64 \t387 23\n  6 98\t215 314\n*   +\t*   +";
+        let blocks = parse_blocks(input).unwrap();
+        assert_eq!(4, blocks.len());
+        assert_eq!(
+            vec![0, 4, 8, 12],
+            blocks.iter().map(|b| b.column).collect::<Vec<_>>()
+        );
+        assert_eq!(4277556, process_part1(&blocks));
+        assert_eq!(3263827, process_part2(&blocks));
+
+        let blocks = parse_blocks("1    2\r\n3    4\r\n+    *\r\n").unwrap();
+        assert_eq!(2, blocks.len());
+        assert_eq!(vec![1, 3], blocks[0].row_values().unwrap());
+        assert_eq!(vec![2, 4], blocks[1].row_values().unwrap());
+    }
+
+    #[test]
+    fn test_parse_block_errors() {
+        let error = parse_blocks("1 2\n3 4").unwrap_err();
+        assert_eq!(
+            "Missing operator row, the last line contains digits",
+            error.to_string()
+        );
+        let error = parse_blocks("1 2 3\n3 4 5\n+   *").unwrap_err();
+        assert_eq!("Missing operator for block at column 2", error.to_string());
+        assert!(parse_blocks("").is_err());
+    }
+
+    #[test]
+    fn test_readings() {
+        let blocks = parse_blocks(INPUT).unwrap();
+        let block = &blocks[0];
+        let values = |reading: &str| block.values(reading.parse::<Reading>().unwrap()).unwrap();
+
+        assert_eq!(vec![123, 45, 6], values("rows"));
+        assert_eq!(vec![6, 45, 123], values("rows,reverse-order"));
+        assert_eq!(vec![321, 54, 6], values("rows,reverse-digits"));
+        assert_eq!(vec![1, 24, 356], values("columns"));
+        assert_eq!(vec![356, 24, 1], values("columns,reverse-order"));
+        assert_eq!(
+            vec![653, 42, 1],
+            values("columns, reverse-order, reverse-digits")
+        );
+        assert_eq!(vec![146, 25, 3], values("diagonals"));
+        assert_eq!(
+            vec![3, 52, 641],
+            values("diagonals,reverse-order,reverse-digits")
+        );
+        assert_eq!(vec![1, 2, 34, 5, 6], values("anti-diagonals"));
+
+        let reading = Reading::new(Axis::Columns).reverse_order();
+        let result = process(&blocks, reading, &Operators::default()).unwrap();
+        assert_eq!(3263827, result);
+
+        assert!("columns,upside-down".parse::<Reading>().is_err());
+        assert!("spiral".parse::<Reading>().is_err());
+    }
+
+    #[test]
+    fn test_render_worksheet() {
+        let blocks = parse_blocks(INPUT).unwrap();
+        let operators = Operators::default();
+        let expected = r#"123 328  51 64
+ 45 64  387 23
+  6 98  215 314
+*   +   *   +
+--- --- --- ---
+block 1 at column 0
+    rows:    123 * 45 * 6 = 33210
+    columns: 1 * 24 * 356 = 8544
+block 2 at column 4
+    rows:    328 + 64 + 98 = 490
+    columns: 369 + 248 + 8 = 625
+block 3 at column 8
+    rows:    51 * 387 * 215 = 4243455
+    columns: 32 * 581 * 175 = 3253600
+block 4 at column 12
+    rows:    64 + 23 + 314 = 401
+    columns: 623 + 431 + 4 = 1058
+"#;
+        assert_eq!(expected, Worksheet::new(&blocks, &operators).to_string());
+    }
+
+    #[test]
+    fn test_render_errors() {
+        let blocks = parse_blocks("3 1\n4 2\n- %").unwrap();
+        let rendered = Worksheet::new(&blocks, &Operators::default()).to_string();
+        assert!(
+            rendered.contains("rows:    3 - 4 = error: Failed to subtract 3 and 4 at column 0")
+        );
+        assert!(rendered.contains("rows:    1 % 2 = error: Unknown operator '%' at column 2"));
+    }
 }
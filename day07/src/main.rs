@@ -3,14 +3,21 @@
 #![allow(unused_mut)]
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::{Display, Formatter, Result},
+    io::Write,
 };
 
+use num_bigint::BigUint;
+
 const EMPTY: u8 = b'.';
 const START: u8 = b'S';
 const SPLITTER: u8 = b'^';
 const BEAM: u8 = b'|';
+const SPLITTER3: u8 = b'*';
+const MIRROR_LEFT: u8 = b'/';
+const MIRROR_RIGHT: u8 = b'\\';
+const ABSORBER: u8 = b'#';
 
 #[derive(Debug, Clone, Copy, PartialEq, Hash, Eq)]
 struct Pos {
@@ -22,6 +29,63 @@ impl Pos {
     pub fn new(x: i32, y: i32) -> Self {
         Self { x, y }
     }
+
+    pub fn step(&self, dir: Dir) -> Self {
+        let (x, y) = dir.delta();
+        Self::new(self.x + x, self.y + y)
+    }
+}
+
+/// The direction a beam travels in.
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq)]
+enum Dir {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Dir {
+    pub fn delta(&self) -> (i32, i32) {
+        match self {
+            Dir::Up => (0, -1),
+            Dir::Down => (0, 1),
+            Dir::Left => (-1, 0),
+            Dir::Right => (1, 0),
+        }
+    }
+
+    /// The offset to the side of the beam, splitters move beams sideways.
+    pub fn side(&self) -> (i32, i32) {
+        match self {
+            Dir::Up | Dir::Down => (1, 0),
+            Dir::Left | Dir::Right => (0, 1),
+        }
+    }
+
+    /// Deflects the beam on a `/` or `\` mirror.
+    pub fn reflect(&self, mirror: u8) -> Self {
+        match (mirror, self) {
+            (MIRROR_LEFT, Dir::Down) | (MIRROR_RIGHT, Dir::Up) => Dir::Left,
+            (MIRROR_LEFT, Dir::Up) | (MIRROR_RIGHT, Dir::Down) => Dir::Right,
+            (MIRROR_LEFT, Dir::Right) | (MIRROR_RIGHT, Dir::Left) => Dir::Up,
+            (MIRROR_LEFT, Dir::Left) | (MIRROR_RIGHT, Dir::Right) => Dir::Down,
+            _ => *self,
+        }
+    }
+}
+
+/// A beam entering a field of the grid, travelling in a direction.
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq)]
+struct Beam {
+    pos: Pos,
+    dir: Dir,
+}
+
+impl Beam {
+    pub fn new(pos: Pos, dir: Dir) -> Self {
+        Self { pos, dir }
+    }
 }
 
 #[derive(Clone)]
@@ -29,7 +93,7 @@ struct Grid {
     width: u32,
     height: u32,
     fields: Vec<u8>,
-    start: Option<Pos>,
+    starts: Vec<Pos>,
 }
 
 impl Grid {
@@ -38,7 +102,7 @@ impl Grid {
             width: 0,
             height: 0,
             fields: Vec::new(),
-            start: None,
+            starts: Vec::new(),
         }
     }
 
@@ -48,8 +112,8 @@ impl Grid {
         }
     }
 
-    pub fn set_start(&mut self, pos: Pos) {
-        self.start = Some(pos);
+    pub fn add_start(&mut self, pos: Pos) {
+        self.starts.push(pos);
     }
 
     pub fn add_row(&mut self, line: &[u8]) {
@@ -70,7 +134,67 @@ impl Grid {
     }
 
     pub fn start(&self) -> Option<&Pos> {
-        self.start.as_ref()
+        self.starts.first()
+    }
+
+    pub fn starts(&self) -> &[Pos] {
+        &self.starts
+    }
+
+    /// Passes the beam through its field, returns the beams entering the following fields.
+    ///
+    /// Splitters `^` send the beam into the fields to both sides of it, `*` additionally lets the
+    /// beam pass. Mirrors `/` and `\` deflect the beam, an absorber `#` stops it. The fields to
+    /// the sides of a splitter are entered like any other field, splitters next to each other
+    /// pass the beam back and forth endlessly. `None` marks a beam that left the grid or got
+    /// absorbed, which ends its timeline.
+    fn advance(&self, beam: Beam) -> Vec<Option<Beam>> {
+        let enter = |pos: Pos, dir: Dir| self.get(pos).map(|_| Beam::new(pos, dir));
+        let (dx, dy) = beam.dir.side();
+        let left = Pos::new(beam.pos.x - dx, beam.pos.y - dy);
+        let right = Pos::new(beam.pos.x + dx, beam.pos.y + dy);
+
+        match self.get(beam.pos) {
+            None | Some(ABSORBER) => vec![None],
+            Some(SPLITTER) => vec![enter(left, beam.dir), enter(right, beam.dir)],
+            Some(SPLITTER3) => vec![
+                enter(left, beam.dir),
+                enter(beam.pos.step(beam.dir), beam.dir),
+                enter(right, beam.dir),
+            ],
+            Some(mirror @ (MIRROR_LEFT | MIRROR_RIGHT)) => {
+                let dir = beam.dir.reflect(mirror);
+                vec![enter(beam.pos.step(dir), dir)]
+            }
+            Some(_) => vec![enter(beam.pos.step(beam.dir), beam.dir)],
+        }
+    }
+
+    /// Returns the positions of all splitters, row by row.
+    pub fn splitters(&self) -> impl Iterator<Item = Pos> + '_ {
+        let width = self.width.max(1) as i32;
+        self.fields
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| matches!(**field, SPLITTER | SPLITTER3))
+            .map(move |(index, _)| Pos::new(index as i32 % width, index as i32 / width))
+    }
+
+    /// Returns true if the field holds a splitter.
+    fn is_splitter(&self, pos: Pos) -> bool {
+        matches!(self.get(pos), Some(SPLITTER | SPLITTER3))
+    }
+
+    /// Returns true if [`sweep`] can handle the grid, a single start and splitters `^` that
+    /// are not next to each other.
+    pub fn is_sweepable(&self) -> bool {
+        let width = self.width.max(1) as usize;
+        self.starts.len() == 1
+            && self.fields.chunks(width).all(|row| {
+                row.iter()
+                    .all(|field| matches!(*field, EMPTY | START | BEAM | SPLITTER))
+                    && row.windows(2).all(|pair| pair != [SPLITTER, SPLITTER])
+            })
     }
 
     fn get(&self, Pos { x, y }: Pos) -> Option<u8> {
@@ -97,9 +221,9 @@ impl Display for Grid {
                 let value = char::from(self.fields[(y * self.width + x) as usize]);
                 write!(f, "{}", value)?;
             }
-            writeln!(f, "")?;
+            writeln!(f)?;
         }
-        writeln!(f, "")
+        writeln!(f)
     }
 }
 
@@ -109,94 +233,533 @@ fn parse_input(input: &str) -> Grid {
         .filter(|line| !line.trim().is_empty())
         .enumerate()
         .fold(Grid::new(), |mut grid, (row, line)| {
-            if let Some(index) = line.find('S') {
-                grid.set_start(Pos::new(index as i32, row as i32));
+            for (index, _) in line.match_indices('S') {
+                grid.add_start(Pos::new(index as i32, row as i32));
             }
             grid.add_row(line.as_bytes());
             grid
         })
 }
 
-fn process_part1(mut grid: Grid) -> u64 {
-    let mut total_splits = 0;
-
-    let start = grid.start.expect("Failed to find start");
-    let mut queue = vec![start];
+/// Finds all beams reachable from the starts, each beam mapped to its number of predecessors.
+fn explore(grid: &Grid) -> HashMap<Beam, usize> {
+    let mut beams = HashMap::new();
+    let mut queue = Vec::new();
+    for &start in grid.starts() {
+        let beam = Beam::new(start, Dir::Down);
+        if beams.insert(beam, 0).is_none() {
+            queue.push(beam);
+        }
+    }
 
     while let Some(beam) = queue.pop() {
-        let pos = Pos::new(beam.x, beam.y + 1);
-        if let Some(bottom) = grid.get(pos) {
-            match bottom {
-                b'|' => (),
-                b'^' => {
-                    let left = Pos::new(beam.x - 1, beam.y + 1);
-                    let right = Pos::new(beam.x + 1, beam.y + 1);
-                    queue.push(left);
-                    queue.push(right);
-                    grid.set_beam(left);
-                    grid.set_beam(right);
-                    total_splits += 1;
-                }
-                b'.' => {
-                    queue.push(pos);
-                    grid.set_beam(pos);
-                }
-                _ => (),
+        for next in grid.advance(beam).into_iter().flatten() {
+            if !beams.contains_key(&next) {
+                queue.push(next);
             }
+            *beams.entry(next).or_insert(0) += 1;
         }
     }
 
-    total_splits
+    beams
+}
+
+/// Counts the splitters hit by at least one of the beams.
+fn count_splits<'a>(grid: &Grid, beams: impl Iterator<Item = &'a Beam>) -> u64 {
+    beams
+        .map(|beam| beam.pos)
+        .filter(|pos| grid.is_splitter(*pos))
+        .collect::<HashSet<_>>()
+        .len() as u64
+}
+
+/// Returns all fields beams pass through, including the splitters and absorbers they run into.
+fn beam_fields(grid: &Grid) -> HashSet<Pos> {
+    explore(grid).keys().map(|beam| beam.pos).collect()
+}
+
+/// Splitters that do not contribute to the beams of a manifold.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct SplitterReport {
+    /// Splitters no beam runs into
+    unreachable: Vec<Pos>,
+    /// Splitters hit by a beam, whose removal keeps the same number of fields covered by beams
+    redundant: Vec<Pos>,
 }
 
-fn traverse(grid: &Grid, cache: &mut HashMap<Pos, u64>, x: i32, y: i32) -> u64 {
-    let pos = Pos::new(x, y + 1);
-    match grid.get(pos) {
-        Some(b'.') => {
-            return traverse(grid, cache, x, y + 1);
+/// Checks every splitter of the grid, the redundant ones by removing each and simulating again.
+fn analyze_splitters(grid: &Grid) -> SplitterReport {
+    let fields = beam_fields(grid);
+    let mut report = SplitterReport::default();
+
+    for pos in grid.splitters() {
+        if !fields.contains(&pos) {
+            report.unreachable.push(pos);
+            continue;
         }
-        Some(b'^') => {
-            let left = Pos::new(x - 1, y);
-            let left = if let Some(value) = cache.get(&left) {
-                *value
-            } else {
-                let value = traverse(grid, cache, x - 1, y);
-                cache.insert(left, value);
-                value
-            };
 
-            let right = Pos::new(x + 1, y);
-            let right = if let Some(value) = cache.get(&right) {
-                *value
+        let mut without = grid.clone();
+        if let Some(field) = without.get_mut(pos) {
+            *field = EMPTY;
+        }
+        if beam_fields(&without).len() == fields.len() {
+            report.redundant.push(pos);
+        }
+    }
+
+    report
+}
+
+fn process_part1(grid: Grid) -> u64 {
+    if grid.is_sweepable() {
+        return sweep::<bool>(&grid).expect("Failed to sweep beams").splits;
+    }
+
+    let beams = explore(&grid);
+    count_splits(&grid, beams.keys())
+}
+
+/// A number of timelines, fails to add when the result does not fit.
+trait Count: Clone + Default + Ord + std::fmt::Debug {
+    fn one() -> Self;
+
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+
+    /// The binary logarithm, used to scale counts in a [`Heatmap`].
+    fn log2(&self) -> f64;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+macro_rules! impl_count {
+    ($($t:ty),*) => {
+        $(impl Count for $t {
+            fn one() -> Self {
+                1
+            }
+
+            fn checked_add(&self, other: &Self) -> Option<Self> {
+                <$t>::checked_add(*self, *other)
+            }
+
+            fn log2(&self) -> f64 {
+                (*self as f64).log2()
+            }
+        })*
+    };
+}
+
+impl_count!(u64, u128);
+
+/// Only tracks whether beams reach a field, enough to count the splits.
+impl Count for bool {
+    fn one() -> Self {
+        true
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(*self || *other)
+    }
+
+    fn log2(&self) -> f64 {
+        0.0
+    }
+}
+
+impl Count for BigUint {
+    fn one() -> Self {
+        BigUint::from(1u8)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn log2(&self) -> f64 {
+        // keep the leading 64 bits, the remaining ones only scale the value
+        let shift = self.bits().saturating_sub(64);
+        let leading = (self >> shift).iter_u64_digits().next().unwrap_or(0);
+        (leading as f64).log2() + shift as f64
+    }
+}
+
+/// The timelines of a manifold could not be counted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TimelineError {
+    /// The number of timelines does not fit into the chosen count type
+    Overflow { row: i32 },
+    /// A beam runs in circles, the number of timelines is infinite
+    Loop { pos: Pos },
+}
+
+impl Display for TimelineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            TimelineError::Overflow { row } => {
+                write!(f, "Number of timelines overflows in row {}", row)
+            }
+            TimelineError::Loop { pos } => {
+                write!(f, "Beam loops endlessly through ({}, {})", pos.x, pos.y)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TimelineError {}
+
+/// The result of sweeping the beams through the manifold row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Sweep<C> {
+    /// Number of splitters hit by at least one beam
+    splits: u64,
+    /// Number of timelines, each split doubles the timelines of a beam
+    timelines: C,
+}
+
+/// Sweeps all beams from the start down to the bottom of the grid, one row at a time.
+///
+/// Keeps the number of timelines per column for the current row. A beam hitting a splitter
+/// continues on both sides of it in the same row, a beam leaving the grid at the sides or the
+/// bottom ends its timelines. Timelines double with every level of splitters, use `u128` or
+/// [`BigUint`] for deep manifolds, an overflow is reported as error.
+///
+/// Only supports splitters `^` with beams travelling down from the first start, see
+/// [`Grid::is_sweepable`]. Use [`trace`] for all other elements.
+fn sweep<C: Count>(grid: &Grid) -> std::result::Result<Sweep<C>, TimelineError> {
+    let start = grid.start().expect("Failed to find start");
+    let width = grid.width() as i32;
+
+    let mut splits = 0;
+    let mut finished = C::default();
+    let mut counts = vec![C::default(); width as usize];
+    counts[start.x as usize] = C::one();
+
+    for y in start.y + 1..grid.height() as i32 {
+        let add = |total: &mut C, count: &C| -> std::result::Result<(), TimelineError> {
+            *total = total
+                .checked_add(count)
+                .ok_or(TimelineError::Overflow { row: y })?;
+            Ok(())
+        };
+
+        let mut next = vec![C::default(); width as usize];
+        for (x, count) in counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| !count.is_zero())
+        {
+            let x = x as i32;
+            if grid.get(Pos::new(x, y)) == Some(SPLITTER) {
+                splits += 1;
+                for side in [x - 1, x + 1] {
+                    if 0 <= side && side < width {
+                        add(&mut next[side as usize], count)?;
+                    } else {
+                        add(&mut finished, count)?;
+                    }
+                }
             } else {
-                let value = traverse(grid, cache, x + 1, y);
-                cache.insert(right, value);
-                value
+                add(&mut next[x as usize], count)?;
+            }
+        }
+        counts = next;
+    }
+
+    let mut timelines = finished;
+    for count in counts.iter() {
+        timelines = timelines
+            .checked_add(count)
+            .ok_or(TimelineError::Overflow {
+                row: grid.height() as i32,
+            })?;
+    }
+    Ok(Sweep { splits, timelines })
+}
+
+/// Traces all beams from all starts through the manifold until they leave it or get absorbed.
+///
+/// Beams may travel in any direction, the timelines are counted by passing them along the beams
+/// in topological order. Every beam ending, either by leaving the grid or by getting absorbed,
+/// completes its timelines. A beam running in circles is reported as error.
+fn trace<C: Count>(grid: &Grid) -> std::result::Result<Sweep<C>, TimelineError> {
+    flow(grid, |_, _| Ok(()))
+}
+
+/// Passes the timelines along all beams, calls `visit` with every field and the timelines
+/// passing through it, including the splitters and absorbers the beams run into.
+fn flow<C: Count>(
+    grid: &Grid,
+    mut visit: impl FnMut(Pos, &C) -> std::result::Result<(), TimelineError>,
+) -> std::result::Result<Sweep<C>, TimelineError> {
+    let mut predecessors = explore(grid);
+    let splits = count_splits(grid, predecessors.keys());
+
+    let mut counts: HashMap<Beam, C> = HashMap::new();
+    for &start in grid.starts() {
+        let count = counts.entry(Beam::new(start, Dir::Down)).or_default();
+        *count = count
+            .checked_add(&C::one())
+            .ok_or(TimelineError::Overflow { row: start.y })?;
+    }
+
+    let mut queue = predecessors
+        .iter()
+        .filter(|(_, count)| **count == 0)
+        .map(|(beam, _)| *beam)
+        .collect::<Vec<_>>();
+    let mut visited = 0;
+    let mut timelines = C::default();
+
+    while let Some(beam) = queue.pop() {
+        visited += 1;
+        let count = counts.remove(&beam).unwrap_or_default();
+        visit(beam.pos, &count)?;
+
+        for next in grid.advance(beam) {
+            let overflow = TimelineError::Overflow { row: beam.pos.y };
+            let Some(next) = next else {
+                timelines = timelines.checked_add(&count).ok_or(overflow)?;
+                continue;
             };
 
-            return left + right;
+            let total = counts.entry(next).or_default();
+            *total = total.checked_add(&count).ok_or(overflow)?;
+            let remaining = predecessors.get_mut(&next).expect("Failed to find beam");
+            *remaining -= 1;
+            if *remaining == 0 {
+                queue.push(next);
+            }
         }
-        _ => 1,
     }
+
+    if visited < predecessors.len() {
+        let unresolved = predecessors
+            .iter()
+            .filter(|(_, count)| **count > 0)
+            .map(|(beam, _)| *beam)
+            .collect::<HashSet<_>>();
+        let beam = find_loop(grid, &unresolved)
+            .into_iter()
+            .min_by_key(|beam| (beam.pos.y, beam.pos.x))
+            .expect("Failed to find looping beam");
+        return Err(TimelineError::Loop { pos: beam.pos });
+    }
+
+    Ok(Sweep { splits, timelines })
 }
 
-fn process_part2(grid: Grid) -> u64 {
-    let mut cache = HashMap::new();
-    let start = grid.start().expect("Failed to find start");
-    traverse(&grid, &mut cache, start.x, start.y)
+/// Drops the beams leading out of the unresolved ones, the remaining beams run in circles.
+fn find_loop(grid: &Grid, unresolved: &HashSet<Beam>) -> HashSet<Beam> {
+    let mut successors = HashMap::new();
+    let mut sources: HashMap<Beam, Vec<Beam>> = HashMap::new();
+    for &beam in unresolved {
+        let next = grid.advance(beam).into_iter().flatten();
+        let next = next
+            .filter(|next| unresolved.contains(next))
+            .collect::<Vec<_>>();
+        successors.insert(beam, next.len());
+        for next in next {
+            sources.entry(next).or_default().push(beam);
+        }
+    }
+
+    let mut queue = successors
+        .iter()
+        .filter(|(_, count)| **count == 0)
+        .map(|(beam, _)| *beam)
+        .collect::<Vec<_>>();
+    let mut looping = unresolved.clone();
+    while let Some(beam) = queue.pop() {
+        looping.remove(&beam);
+        for source in sources.get(&beam).into_iter().flatten() {
+            let remaining = successors.get_mut(source).expect("Failed to find beam");
+            *remaining -= 1;
+            if *remaining == 0 {
+                queue.push(*source);
+            }
+        }
+    }
+
+    looping
+}
+
+/// The number of timelines passing through each field of the grid.
+#[derive(Debug, Clone)]
+struct Heatmap<C> {
+    width: u32,
+    height: u32,
+    /// The fields of the grid, shown where no timeline passes
+    fields: Vec<u8>,
+    counts: Vec<C>,
+    /// The binary logarithm of the hottest field
+    max: f64,
+}
+
+impl<C: Count> Heatmap<C> {
+    /// Symbols for the heat of a field, on a logarithmic scale up to the hottest field.
+    const HEAT: &[u8] = b"123456789";
+
+    /// Traces the beams through the grid, see [`trace`].
+    pub fn new(grid: &Grid) -> std::result::Result<Self, TimelineError> {
+        let width = grid.width();
+        let mut counts = vec![C::default(); grid.fields.len()];
+        flow(grid, |pos, count: &C| {
+            let total = &mut counts[(pos.y * width as i32 + pos.x) as usize];
+            *total = total
+                .checked_add(count)
+                .ok_or(TimelineError::Overflow { row: pos.y })?;
+            Ok(())
+        })?;
+
+        let max = counts.iter().map(|count| count.log2()).fold(0.0, f64::max);
+        Ok(Self {
+            width,
+            height: grid.height(),
+            fields: grid.fields.clone(),
+            counts,
+            max,
+        })
+    }
+
+    pub fn get(&self, Pos { x, y }: Pos) -> Option<&C> {
+        if 0 <= x && x < self.width as i32 && 0 <= y && y < self.height as i32 {
+            self.counts.get((y * self.width as i32 + x) as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the splitters ordered by the number of timelines running into them, most first.
+    pub fn splitters(&self) -> Vec<(Pos, &C)> {
+        let mut splitters = self
+            .fields
+            .iter()
+            .zip(self.counts.iter())
+            .enumerate()
+            .filter(|(_, (field, count))| {
+                matches!(**field, SPLITTER | SPLITTER3) && !count.is_zero()
+            })
+            .map(|(index, (_, count))| {
+                let index = index as i32;
+                let width = self.width as i32;
+                (Pos::new(index % width, index / width), count)
+            })
+            .collect::<Vec<_>>();
+        splitters
+            .sort_by(|(l, left), (r, right)| right.cmp(left).then((l.y, l.x).cmp(&(r.y, r.x))));
+        splitters
+    }
+
+    /// The heat of a field between 0.0 and 1.0, `None` if no timeline passes through it.
+    fn heat(&self, count: &C) -> Option<f64> {
+        if count.is_zero() {
+            return None;
+        }
+        if self.max > 0.0 {
+            Some(count.log2() / self.max)
+        } else {
+            Some(1.0)
+        }
+    }
+
+    /// Writes the heatmap as binary PPM image, each field is drawn as a square of `scale` pixels.
+    pub fn write_ppm(&self, out: &mut impl Write, scale: usize) -> std::io::Result<()> {
+        let scale = scale.max(1);
+        writeln!(
+            out,
+            "P6\n{} {}\n255",
+            self.width as usize * scale,
+            self.height as usize * scale
+        )?;
+
+        let width = self.width.max(1) as usize;
+        for (fields, counts) in self.fields.chunks(width).zip(self.counts.chunks(width)) {
+            let row = fields
+                .iter()
+                .zip(counts)
+                .flat_map(|(field, count)| {
+                    let color = match (self.heat(count), *field) {
+                        (Some(heat), _) => {
+                            [(80.0 + 175.0 * heat) as u8, (220.0 * heat * heat) as u8, 40]
+                        }
+                        (None, EMPTY) => [30, 30, 30],
+                        (None, _) => [90, 90, 110],
+                    };
+                    std::iter::repeat_n(color, scale).flatten()
+                })
+                .collect::<Vec<u8>>();
+            for _ in 0..scale {
+                out.write_all(&row)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<C: Count> Display for Heatmap<C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        let width = self.width.max(1) as usize;
+        for (fields, counts) in self.fields.chunks(width).zip(self.counts.chunks(width)) {
+            for (field, count) in fields.iter().zip(counts) {
+                let symbol = match self.heat(count) {
+                    Some(heat) => {
+                        let index = (heat * (Self::HEAT.len() - 1) as f64).round() as usize;
+                        Self::HEAT[index]
+                    }
+                    None => *field,
+                };
+                write!(f, "{}", char::from(symbol))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn process_part2(grid: Grid) -> u128 {
+    let result = if grid.is_sweepable() {
+        sweep::<u128>(&grid)
+    } else {
+        trace::<u128>(&grid)
+    };
+    result.expect("Failed to count timelines").timelines
 }
 
 fn main() {
     let grid = parse_input(include_str!("input.txt"));
     let result = process_part1(grid.clone());
     println!("PART 1: {}", result);
-    let result = process_part2(grid);
+    let result = process_part2(grid.clone());
     println!("PART 2: {}", result);
+
+    // `--heatmap` prints the heatmap, an optional path to write it as PPM image
+    if std::env::args()
+        .nth(1)
+        .is_some_and(|arg| arg == "--heatmap")
+    {
+        let heatmap = Heatmap::<u128>::new(&grid).expect("Failed to trace beams");
+        print!("{}", heatmap);
+        if let Some(path) = std::env::args().nth(2) {
+            let file = std::fs::File::create(&path).expect("Failed to create image");
+            let mut writer = std::io::BufWriter::new(file);
+            heatmap
+                .write_ppm(&mut writer, 4)
+                .expect("Failed to write image");
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{Pos, parse_input, process_part1, process_part2};
+    use num_bigint::BigUint;
+
+    use crate::{
+        Heatmap, Pos, SplitterReport, Sweep, TimelineError, analyze_splitters, parse_input,
+        process_part1, process_part2, sweep, trace,
+    };
 
     const INPUT: &str = r#"
 .......S.......
@@ -236,4 +799,332 @@ mod tests {
         let grid = parse_input(INPUT);
         assert_eq!(40, process_part2(grid));
     }
+
+    #[test]
+    fn test_sweep() {
+        let grid = parse_input(INPUT);
+        let expected = Sweep {
+            splits: 21,
+            timelines: 40,
+        };
+        assert_eq!(expected, sweep::<u64>(&grid).unwrap());
+    }
+
+    #[test]
+    fn test_sweep_tall_manifold() {
+        // a very tall manifold, the recursion used to overflow the stack
+        let mut input = String::from("...S...\n");
+        for row in 0..200_000 {
+            let line = match row {
+                0 => "...^...\n",
+                500 => "..^.^..\n",
+                _ => ".......\n",
+            };
+            input.push_str(line);
+        }
+
+        let grid = parse_input(&input);
+        let result = sweep::<u64>(&grid).unwrap();
+        assert_eq!(3, result.splits);
+        assert_eq!(4, result.timelines);
+        assert_eq!(result.splits, process_part1(grid));
+    }
+
+    /// Generates a manifold where every beam hits a splitter on each of the `levels` rows.
+    fn galton_board(levels: usize) -> String {
+        let width = 2 * levels + 3;
+        let center = levels + 1;
+        let mut rows = vec![vec![b'.'; width]];
+        rows[0][center] = b'S';
+        for level in 0..levels {
+            let mut row = vec![b'.'; width];
+            for x in (center - level..=center + level).step_by(2) {
+                row[x] = b'^';
+            }
+            rows.push(row);
+        }
+        rows.push(vec![b'.'; width]);
+
+        let rows = rows.into_iter().map(|row| String::from_utf8(row).unwrap());
+        rows.collect::<Vec<_>>().join("\n")
+    }
+
+    #[test]
+    fn test_sweep_deep_manifold() {
+        let grid = parse_input(&galton_board(100));
+        assert!(matches!(
+            sweep::<u64>(&grid),
+            Err(TimelineError::Overflow { .. })
+        ));
+
+        let result = sweep::<u128>(&grid).unwrap();
+        assert_eq!(5050, result.splits);
+        assert_eq!(1u128 << 100, result.timelines);
+
+        let grid = parse_input(&galton_board(200));
+        assert!(sweep::<u128>(&grid).is_err());
+        let result = sweep::<BigUint>(&grid).unwrap();
+        assert_eq!(BigUint::from(1u8) << 200, result.timelines);
+    }
+
+    #[test]
+    fn test_trace_matches_sweep() {
+        let grid = parse_input(INPUT);
+        assert_eq!(sweep::<u64>(&grid), trace::<u64>(&grid));
+
+        let grid = parse_input(&galton_board(30));
+        assert_eq!(sweep::<u64>(&grid), trace::<u64>(&grid));
+    }
+
+    #[test]
+    fn test_is_sweepable() {
+        assert!(parse_input(INPUT).is_sweepable());
+        assert!(parse_input(".S.\n...\n^.^\n").is_sweepable());
+        assert!(!parse_input(".S..\n.^^.\n....\n").is_sweepable());
+        assert!(!parse_input(".S.\n.*.\n...\n").is_sweepable());
+        assert!(!parse_input(".S.\n.\\.\n...\n").is_sweepable());
+        assert!(!parse_input(".S.\n...\n.S.\n").is_sweepable());
+
+        // only reachability is tracked, deep manifolds do not overflow
+        let grid = parse_input(&galton_board(200));
+        assert!(grid.is_sweepable());
+        assert_eq!(20100, sweep::<bool>(&grid).unwrap().splits);
+        assert_eq!(20100, process_part1(grid));
+    }
+
+    #[test]
+    fn test_trace_mirrors_and_absorbers() {
+        // the beam gets deflected to the right, then back down into a splitter
+        let input = r#"
+.S....
+.\..\.
+....^.
+..#...
+"#;
+        let grid = parse_input(input);
+        let expected = Sweep {
+            splits: 1,
+            timelines: 2,
+        };
+        assert_eq!(Ok(expected), trace::<u64>(&grid));
+
+        // the absorber ends one of the timelines
+        let input = r#"
+..S..
+..^..
+.#...
+.....
+"#;
+        let grid = parse_input(input);
+        assert_eq!(Ok(2), trace::<u64>(&grid).map(|sweep| sweep.timelines));
+        assert_eq!(1, process_part1(grid));
+    }
+
+    #[test]
+    fn test_trace_three_way_splitter() {
+        let input = r#"
+..S..
+..*..
+.....
+.^.^.
+.....
+"#;
+        let grid = parse_input(input);
+        let expected = Sweep {
+            splits: 3,
+            timelines: 5,
+        };
+        assert_eq!(Ok(expected), trace::<u64>(&grid));
+
+        // the upper beam passes through the lower start, both keep their timelines
+        let grid = parse_input(".S.\n...\n.S.\n...\n");
+        assert_eq!(Ok(2), trace::<u64>(&grid).map(|sweep| sweep.timelines));
+    }
+
+    #[test]
+    fn test_trace_beside_splitter() {
+        // the beam to the right gets absorbed, no timeline continues below the absorber
+        let heatmap = Heatmap::<u64>::new(&parse_input(".S.\n.^#\n...\n")).unwrap();
+        assert_eq!(Some(&1), heatmap.get(Pos::new(2, 1)));
+        assert_eq!(Some(&0), heatmap.get(Pos::new(2, 2)));
+        assert_eq!(Some(&1), heatmap.get(Pos::new(0, 2)));
+
+        // the mirrors deflect the beams to the sides of the splitters
+        let heatmap = Heatmap::<u64>::new(&parse_input(".S..\n.^\\.\n....\n")).unwrap();
+        assert_eq!(Some(&1), heatmap.get(Pos::new(3, 1)));
+        assert_eq!(Some(&0), heatmap.get(Pos::new(2, 2)));
+        let heatmap = Heatmap::<u64>::new(&parse_input("..S.\n./^.\n....\n")).unwrap();
+        assert_eq!(Some(&1), heatmap.get(Pos::new(0, 1)));
+        assert_eq!(Some(&0), heatmap.get(Pos::new(1, 2)));
+
+        // splitters next to each other split the beam, then pass it back and forth
+        for input in [".S..\n.^^.\n....\n", ".S..\n.^*.\n....\n"] {
+            let grid = parse_input(input);
+            assert_eq!(
+                Err(TimelineError::Loop {
+                    pos: Pos::new(1, 1)
+                }),
+                trace::<u64>(&grid)
+            );
+            assert_eq!(2, process_part1(grid));
+        }
+
+        // the beam passing the three-way splitter enters the splitter beside the other one
+        let input = r#"
+..S..
+..^..
+.*...
+.....
+"#;
+        let grid = parse_input(input);
+        let expected = Sweep {
+            splits: 2,
+            timelines: 4,
+        };
+        assert_eq!(Ok(expected), trace::<u64>(&grid));
+    }
+
+    #[test]
+    fn test_trace_multiple_starts() {
+        let input = r#"
+.S...S.
+.......
+.^...^.
+.......
+...S...
+"#;
+        let grid = parse_input(input);
+        assert_eq!(3, grid.starts().len());
+        let expected = Sweep {
+            splits: 2,
+            timelines: 5,
+        };
+        assert_eq!(Ok(expected), trace::<u64>(&grid));
+    }
+
+    #[test]
+    fn test_trace_horizontal_splitter() {
+        // the horizontal beam gets split up and down by the splitter
+        let input = r#"
+.S....
+.\..^.
+......
+"#;
+        let grid = parse_input(input);
+        let expected = Sweep {
+            splits: 1,
+            timelines: 2,
+        };
+        assert_eq!(Ok(expected), trace::<u64>(&grid));
+    }
+
+    #[test]
+    fn test_trace_loop() {
+        // the split beams get caught in a loop of mirrors
+        let input = r#"
+..S..
+./.\.
+..^..
+.....
+.\./.
+"#;
+        let grid = parse_input(input);
+        assert_eq!(
+            Err(TimelineError::Loop {
+                pos: Pos::new(1, 1)
+            }),
+            trace::<u64>(&grid)
+        );
+        assert_eq!(1, process_part1(grid));
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let grid = parse_input(INPUT);
+        let heatmap = Heatmap::<u64>::new(&grid).unwrap();
+        assert_eq!(Some(&1), heatmap.get(Pos::new(7, 0)));
+        assert_eq!(Some(&1), heatmap.get(Pos::new(7, 2)));
+        assert_eq!(Some(&2), heatmap.get(Pos::new(7, 4)));
+        assert_eq!(Some(&0), heatmap.get(Pos::new(0, 0)));
+        assert_eq!(None, heatmap.get(Pos::new(15, 0)));
+
+        // the timelines leaving the bottom row add up to all timelines
+        let bottom = (0..15).map(|x| heatmap.get(Pos::new(x, 15)).unwrap());
+        assert_eq!(40, bottom.sum::<u64>());
+
+        let splitters = heatmap.splitters();
+        assert_eq!(21, splitters.len());
+        assert_eq!((Pos::new(7, 14), &7), splitters[0]);
+    }
+
+    #[test]
+    fn test_heatmap_display() {
+        let input = r#"
+..S..
+..^..
+.....
+.^.^.
+.....
+"#;
+        let heatmap = Heatmap::<u64>::new(&parse_input(input)).unwrap();
+        let expected = "..1..\n.111.\n.1.1.\n11911\n1.9.1\n";
+        assert_eq!(expected, heatmap.to_string());
+    }
+
+    #[test]
+    fn test_heatmap_write_ppm() {
+        let heatmap = Heatmap::<u64>::new(&parse_input(INPUT)).unwrap();
+        let mut out = Vec::new();
+        heatmap.write_ppm(&mut out, 2).unwrap();
+        let header = b"P6\n30 32\n255\n";
+        assert!(out.starts_with(header));
+        assert_eq!(header.len() + 30 * 32 * 3, out.len());
+    }
+
+    #[test]
+    fn test_analyze_splitters() {
+        let report = analyze_splitters(&parse_input(INPUT));
+        assert_eq!(vec![Pos::new(9, 14)], report.unreachable);
+        assert!(report.redundant.is_empty());
+
+        // the beams of the lower start cover the fields below the first splitter
+        let input = r#"
+SSS..
+.^...
+.S...
+.....
+...^.
+"#;
+        let expected = SplitterReport {
+            unreachable: vec![Pos::new(3, 4)],
+            redundant: vec![Pos::new(1, 1)],
+        };
+        assert_eq!(expected, analyze_splitters(&parse_input(input)));
+
+        // the beams move below the splitter, covering as many fields as the absorbed ones
+        let input = r#"
+.S.
+.^.
+#.#
+...
+...
+...
+"#;
+        let expected = SplitterReport {
+            unreachable: vec![],
+            redundant: vec![Pos::new(1, 1)],
+        };
+        assert_eq!(expected, analyze_splitters(&parse_input(input)));
+
+        // the splitter beside the other one gets hit by the beam sent to its side
+        let input = r#"
+.S..
+.^^.
+....
+"#;
+        assert_eq!(
+            SplitterReport::default(),
+            analyze_splitters(&parse_input(input))
+        );
+    }
 }
@@ -1,190 +1,792 @@
 #![allow(dead_code)]
 
 use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
     fmt::{Display, Formatter},
+    io::Write,
     str::FromStr,
 };
 
 use itertools::Itertools;
 
+/// Two junctions referred to by index, the lower index first.
+///
+/// Pairs are always ordered by distance first, pairs at the same distance by the index of
+/// their first, then by the index of their second junction. This is the order the input lists
+/// them in, it decides which pairs are connected when several share the same distance.
 type Pair = (usize, usize);
 
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
-struct Vec3 {
-    x: i64,
-    y: i64,
-    z: i64,
+/// A junction box position with `D` coordinates.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd)]
+struct Point<const D: usize> {
+    coords: [i64; D],
 }
 
-impl Display for Vec3 {
+/// The junction boxes of the puzzle are located in three dimensions.
+type Vec3 = Point<3>;
+
+impl<const D: usize> Display for Point<D> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "({:3},{:3},{:3})", self.x, self.y, self.z)
+        let mut coords = self.coords.iter().map(|coord| format!("{:3}", coord));
+        write!(f, "({})", coords.join(","))
     }
 }
 
-impl std::ops::Sub for &Vec3 {
-    type Output = Vec3;
+impl<const D: usize> std::ops::Sub for &Point<D> {
+    type Output = Point<D>;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        Vec3 {
-            x: self.x - rhs.x,
-            y: self.y - rhs.y,
-            z: self.z - rhs.z,
-        }
+        Point::new(std::array::from_fn(|axis| {
+            self.coords[axis] - rhs.coords[axis]
+        }))
     }
 }
 
-impl Vec3 {
-    /// Returns the length of the Vec3.
+impl<const D: usize> Point<D> {
+    pub fn new(coords: [i64; D]) -> Self {
+        Self { coords }
+    }
+
+    /// Returns the length of the Point.
     fn length(&self) -> f64 {
-        let Self { x, y, z } = self;
-        ((x * x + y * y + z * z) as f64).sqrt()
+        (Euclidean.distance(&self.coords, &[0; D]) as f64).sqrt()
     }
 
-    /// Calculates the distance between two Vec3
-    fn distance(&self, rhs: &Vec3) -> f64 {
+    /// Calculates the distance between two Points
+    fn distance(&self, rhs: &Point<D>) -> f64 {
         (rhs - self).length()
     }
 
+    /// Returns the coordinate along the given axis, 0 is x, 1 is y, 2 is z.
+    fn axis(&self, axis: usize) -> i64 {
+        self.coords[axis]
+    }
+
     /// Returns the squared distance
-    fn distance_squared(&self, rhs: &Vec3) -> u64 {
-        let Self { x, y, z } = rhs - self;
-        (x * x + y * y + z * z) as u64
+    fn distance_squared(&self, rhs: &Point<D>) -> u64 {
+        Euclidean.distance(&self.coords, &rhs.coords)
     }
 }
 
-impl FromStr for Vec3 {
+impl<const D: usize> FromStr for Point<D> {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let parts = s.split(',').collect::<Vec<_>>();
-        if parts.len() != 3 {
-            return Err("Failed to parse 3 elements".to_string());
+        if parts.len() != D {
+            return Err(format!("Failed to parse {} elements", D));
         }
 
-        let numbers = parts
-            .iter()
-            .map(|part| part.parse::<i64>().expect("Not a number"))
-            .collect::<Vec<_>>();
-        Ok(Self {
-            x: numbers[0],
-            y: numbers[1],
-            z: numbers[2],
-        })
+        let mut coords = [0; D];
+        for (coord, part) in coords.iter_mut().zip(parts) {
+            *coord = part.trim().parse::<i64>().map_err(|_| "Not a number")?;
+        }
+        Ok(Self { coords })
     }
 }
 
 fn parse(input: &str) -> Vec<Vec3> {
+    parse_points(input)
+}
+
+/// Parses junctions with `D` coordinates each.
+fn parse_points<const D: usize>(input: &str) -> Vec<Point<D>> {
     input
         .lines()
         .map(|line| line.trim())
         .filter(|line| !line.is_empty())
-        .map(|line| line.parse::<Vec3>().expect("Failed to parse junction"))
+        .map(|line| line.parse::<Point<D>>().expect("Failed to parse junction"))
         .collect::<Vec<_>>()
 }
 
-/// Connect junctions by shortest distance, group them into circuits.
+/// Measures the distance between two junctions.
 ///
-/// Keep list of all circuits, stop when the given number of connections have been reached.
-fn process_part1(junctions: &[Vec3], num_pairs: usize, largest: usize) -> u64 {
-    // Generate all pairings, sorted by distance between positions
-    let pairings = junctions
+/// Distances only need to be ordered like the real distances, e.g. the Euclidean metric
+/// returns the squared distance.
+trait Metric {
+    fn distance(&self, lhs: &[i64], rhs: &[i64]) -> u64;
+
+    /// The lowest distance of two junctions whose coordinates differ by `delta` along one axis.
+    ///
+    /// Lets a [`KdTree`] skip parts of the tree, 0 is always safe but searches the whole tree.
+    fn axis_bound(&self, delta: i64) -> u64 {
+        let _ = delta;
+        0
+    }
+}
+
+/// The straight line distance, returned squared.
+#[derive(Debug, Clone, Copy, Default)]
+struct Euclidean;
+
+/// The sum of the distances along all axes.
+#[derive(Debug, Clone, Copy, Default)]
+struct Manhattan;
+
+/// The largest distance along any of the axes.
+#[derive(Debug, Clone, Copy, Default)]
+struct Chebyshev;
+
+impl Metric for Euclidean {
+    fn distance(&self, lhs: &[i64], rhs: &[i64]) -> u64 {
+        lhs.iter()
+            .zip(rhs)
+            .map(|(l, r)| (l - r).unsigned_abs().pow(2))
+            .sum()
+    }
+
+    fn axis_bound(&self, delta: i64) -> u64 {
+        delta.unsigned_abs().pow(2)
+    }
+}
+
+impl Metric for Manhattan {
+    fn distance(&self, lhs: &[i64], rhs: &[i64]) -> u64 {
+        lhs.iter().zip(rhs).map(|(l, r)| l.abs_diff(*r)).sum()
+    }
+
+    fn axis_bound(&self, delta: i64) -> u64 {
+        delta.unsigned_abs()
+    }
+}
+
+impl Metric for Chebyshev {
+    fn distance(&self, lhs: &[i64], rhs: &[i64]) -> u64 {
+        lhs.iter()
+            .zip(rhs)
+            .map(|(l, r)| l.abs_diff(*r))
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn axis_bound(&self, delta: i64) -> u64 {
+        delta.unsigned_abs()
+    }
+}
+
+/// Any function can be used as metric, without a bound the whole [`KdTree`] is searched.
+impl<F: Fn(&[i64], &[i64]) -> u64> Metric for F {
+    fn distance(&self, lhs: &[i64], rhs: &[i64]) -> u64 {
+        self(lhs, rhs)
+    }
+}
+
+/// Disjoint sets of junctions connected into circuits, junctions are referred to by index.
+///
+/// Uses path compression and union by size, merging two circuits is nearly constant.
+#[derive(Debug, Clone)]
+struct Circuits {
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+    /// Number of distinct circuits
+    count: usize,
+}
+
+impl Circuits {
+    /// Creates a separate circuit for each of the junctions.
+    pub fn new(len: usize) -> Self {
+        Self {
+            parents: (0..len).collect(),
+            sizes: vec![1; len],
+            count: len,
+        }
+    }
+
+    /// Returns the representative junction of the circuit the junction belongs to.
+    pub fn find(&mut self, junction: usize) -> usize {
+        let mut root = junction;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+
+        let mut current = junction;
+        while self.parents[current] != root {
+            let next = self.parents[current];
+            self.parents[current] = root;
+            current = next;
+        }
+        root
+    }
+
+    /// Connects the circuits of both junctions, returns false if they already share one.
+    pub fn union(&mut self, lhs: usize, rhs: usize) -> bool {
+        let (mut lhs, mut rhs) = (self.find(lhs), self.find(rhs));
+        if lhs == rhs {
+            return false;
+        }
+        if self.sizes[lhs] < self.sizes[rhs] {
+            std::mem::swap(&mut lhs, &mut rhs);
+        }
+        self.parents[rhs] = lhs;
+        self.sizes[lhs] += self.sizes[rhs];
+        self.count -= 1;
+        true
+    }
+
+    /// Returns true if both junctions belong to the same circuit.
+    pub fn connected(&mut self, lhs: usize, rhs: usize) -> bool {
+        self.find(lhs) == self.find(rhs)
+    }
+
+    /// Returns the number of junctions in the circuit of the junction.
+    pub fn size(&mut self, junction: usize) -> usize {
+        let root = self.find(junction);
+        self.sizes[root]
+    }
+
+    /// Returns the number of distinct circuits.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the sizes of all circuits, largest first.
+    pub fn sizes(&self) -> Vec<usize> {
+        (0..self.parents.len())
+            .filter(|&junction| self.parents[junction] == junction)
+            .map(|root| self.sizes[root])
+            .sorted_by(|l, r| r.cmp(l))
+            .collect()
+    }
+
+    /// Returns the junctions of each circuit, ordered by their smallest junction.
+    pub fn members(&mut self) -> Vec<Vec<usize>> {
+        let mut circuits: Vec<Vec<usize>> = Vec::with_capacity(self.count);
+        let mut index = vec![usize::MAX; self.parents.len()];
+        for junction in 0..self.parents.len() {
+            let root = self.find(junction);
+            if index[root] == usize::MAX {
+                index[root] = circuits.len();
+                circuits.push(Vec::new());
+            }
+            circuits[index[root]].push(junction);
+        }
+        circuits
+    }
+}
+
+/// Generates all pairings of junctions, sorted by distance, then by indices, see [`Pair`].
+///
+/// Materializes all pairs, only used to check [`NearestPairs`].
+fn sorted_pairs<const D: usize>(junctions: &[Point<D>], metric: &impl Metric) -> Vec<(u64, Pair)> {
+    junctions
         .iter()
+        .enumerate()
         .tuple_combinations()
-        .map(|(l, r)| (l.distance_squared(r), (l, r)))
-        .sorted_by(|l, r| l.0.partial_cmp(&r.0).unwrap())
-        .collect::<Vec<_>>();
-
-    // Collect all circuits.
-    let mut circuits: Vec<Vec<&Vec3>> = junctions.iter().map(|j| vec![j]).collect_vec();
-    for (_, (lhs, rhs)) in pairings.into_iter().take(num_pairs) {
-        let left = circuits.iter().position(|c| c.contains(&lhs));
-        let right = circuits.iter().position(|c| c.contains(&rhs));
-
-        match (left, right) {
-            (Some(l), None) => {
-                circuits[l].push(rhs);
+        .map(|((i, l), (j, r))| (metric.distance(&l.coords, &r.coords), (i, j)))
+        .sorted_unstable()
+        .collect::<Vec<_>>()
+}
+
+/// Returns the groups of junctions sharing the same position, ordered by their first index.
+fn duplicates<const D: usize>(junctions: &[Point<D>]) -> Vec<Vec<usize>> {
+    let mut positions: HashMap<&Point<D>, Vec<usize>> = HashMap::new();
+    for (index, junction) in junctions.iter().enumerate() {
+        positions.entry(junction).or_default().push(index);
+    }
+    positions
+        .into_values()
+        .filter(|indices| indices.len() > 1)
+        .sorted()
+        .collect()
+}
+
+/// The pairs sharing the distance of the last pair connected.
+///
+/// If some of them are connected and others are not, the tie-breaking order of [`Pair`]
+/// decides which ones are connected. The cutoff is ambiguous if another choice of the same
+/// number of tied pairs results in different circuit sizes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cutoff {
+    distance: u64,
+    /// Tied pairs that are connected
+    taken: Vec<Pair>,
+    /// Tied pairs that are left out
+    skipped: Vec<Pair>,
+    /// Another choice of tied pairs changes the circuit sizes
+    ambiguous: bool,
+}
+
+impl Cutoff {
+    /// The largest number of choices among the tied pairs that are checked one by one.
+    const MAX_CHOICES: u128 = 10_000;
+
+    /// Finds the pairs tied with the last of the given number of pairs, `None` without pairs.
+    pub fn find<const D: usize>(
+        junctions: &[Point<D>],
+        num_pairs: usize,
+        metric: impl Metric,
+    ) -> Option<Self> {
+        let mut pairs = NearestPairs::new(junctions, metric);
+        let connected = pairs.by_ref().take(num_pairs).collect::<Vec<_>>();
+        let &(distance, _) = connected.last()?;
+
+        let skipped = pairs
+            .take_while(|(other, _)| *other == distance)
+            .map(|(_, pair)| pair)
+            .collect::<Vec<_>>();
+        let tied = connected
+            .iter()
+            .rev()
+            .take_while(|(other, _)| *other == distance)
+            .count();
+        let (before, taken) = connected.split_at(connected.len() - tied);
+        let taken = taken.iter().map(|(_, pair)| *pair).collect::<Vec<_>>();
+
+        let mut circuits = Circuits::new(junctions.len());
+        for (_, (lhs, rhs)) in before {
+            circuits.union(*lhs, *rhs);
+        }
+        let ambiguous = Self::changes_sizes(&circuits, &taken, &skipped);
+
+        Some(Self {
+            distance,
+            taken,
+            skipped,
+            ambiguous,
+        })
+    }
+
+    /// Checks if connecting another choice of tied pairs to the circuits results in different
+    /// circuit sizes than connecting the taken ones.
+    ///
+    /// Tries every choice, with too many of them the cutoff is considered ambiguous.
+    fn changes_sizes(circuits: &Circuits, taken: &[Pair], skipped: &[Pair]) -> bool {
+        if skipped.is_empty() {
+            return false;
+        }
+        let tied = taken.iter().chain(skipped).collect::<Vec<_>>();
+        if Self::num_choices(tied.len(), skipped.len()) > Self::MAX_CHOICES {
+            return true;
+        }
+
+        let sizes = |pairs: &[&Pair]| {
+            let mut circuits = circuits.clone();
+            for (lhs, rhs) in pairs {
+                circuits.union(*lhs, *rhs);
+            }
+            circuits.sizes()
+        };
+        let expected = sizes(&tied[..taken.len()]);
+        tied.into_iter()
+            .combinations(taken.len())
+            .any(|choice| sizes(&choice) != expected)
+    }
+
+    /// Returns the binomial coefficient, saturates at `u128::MAX`.
+    fn num_choices(len: usize, k: usize) -> u128 {
+        let k = k.min(len - k) as u128;
+        (0..k).fold(1u128, |result, i| {
+            result.saturating_mul(len as u128 - i) / (i + 1)
+        })
+    }
+
+    /// Returns true if another choice of the tied pairs changes the circuit sizes.
+    pub fn is_ambiguous(&self) -> bool {
+        self.ambiguous
+    }
+
+    /// Returns all tied pairs, connected ones first.
+    pub fn pairs(&self) -> impl Iterator<Item = &Pair> {
+        self.taken.iter().chain(self.skipped.iter())
+    }
+}
+
+/// A k-d tree over the junctions, splits cycle through all axes.
+///
+/// The tree is stored implicitly, the median of each range is the node splitting it.
+struct KdTree<'a, const D: usize, M> {
+    junctions: &'a [Point<D>],
+    nodes: Vec<usize>,
+    metric: M,
+}
+
+impl<'a, const D: usize, M: Metric> KdTree<'a, D, M> {
+    pub fn new(junctions: &'a [Point<D>], metric: M) -> Self {
+        let mut nodes = (0..junctions.len()).collect::<Vec<_>>();
+        Self::build(junctions, &mut nodes, 0);
+        Self {
+            junctions,
+            nodes,
+            metric,
+        }
+    }
+
+    fn build(junctions: &[Point<D>], nodes: &mut [usize], depth: usize) {
+        if nodes.len() <= 1 || D == 0 {
+            return;
+        }
+        let axis = depth % D;
+        let mid = nodes.len() / 2;
+        nodes.select_nth_unstable_by_key(mid, |&index| junctions[index].axis(axis));
+        let (left, right) = nodes.split_at_mut(mid);
+        Self::build(junctions, left, depth + 1);
+        Self::build(junctions, &mut right[1..], depth + 1);
+    }
+
+    /// Returns the `k` nearest junctions to the given junction including itself, ordered by
+    /// distance, then by index.
+    pub fn nearest(&self, junction: usize, k: usize) -> Vec<(u64, usize)> {
+        let mut found = BinaryHeap::with_capacity(k + 1);
+        self.search(junction, k, 0..self.nodes.len(), 0, &mut found);
+        found.into_sorted_vec()
+    }
+
+    fn search(
+        &self,
+        junction: usize,
+        k: usize,
+        range: std::ops::Range<usize>,
+        depth: usize,
+        found: &mut BinaryHeap<(u64, usize)>,
+    ) {
+        if range.is_empty() || k == 0 {
+            return;
+        }
+        let mid = range.start + range.len() / 2;
+        let node = self.nodes[mid];
+        let target = &self.junctions[junction];
+
+        let distance = self
+            .metric
+            .distance(&target.coords, &self.junctions[node].coords);
+        found.push((distance, node));
+        if found.len() > k {
+            found.pop();
+        }
+        if D == 0 {
+            self.search(junction, k, range.start..mid, depth, found);
+            self.search(junction, k, mid + 1..range.end, depth, found);
+            return;
+        }
+
+        let axis = depth % D;
+        let delta = target.axis(axis) - self.junctions[node].axis(axis);
+        let (near, far) = if delta < 0 {
+            (range.start..mid, mid + 1..range.end)
+        } else {
+            (mid + 1..range.end, range.start..mid)
+        };
+        self.search(junction, k, near, depth + 1, found);
+
+        // the far side can only hold nearer junctions when the splitting plane is close enough
+        let bound = self.metric.axis_bound(delta);
+        if found.len() < k || found.peek().is_some_and(|&(worst, _)| bound <= worst) {
+            self.search(junction, k, far, depth + 1, found);
+        }
+    }
+}
+
+/// The neighbors of a junction with a larger index, fetched from the tree in growing batches.
+struct Neighbors {
+    /// Number of nearest junctions fetched from the tree
+    k: usize,
+    found: Vec<(u64, usize)>,
+    next: usize,
+}
+
+/// Generates the pairs of junctions lazily, ordered by distance, then by their indices.
+///
+/// Each junction keeps the stream of its nearest neighbors, a priority queue holds the next
+/// pair of each stream. Only the pairs taken are ever computed, without sorting all of them.
+struct NearestPairs<'a, const D: usize, M> {
+    tree: KdTree<'a, D, M>,
+    neighbors: Vec<Neighbors>,
+    queue: BinaryHeap<Reverse<(u64, usize, usize)>>,
+}
+
+impl<'a, const D: usize, M: Metric> NearestPairs<'a, D, M> {
+    /// The number of nearest junctions fetched at first.
+    const BATCH: usize = 8;
+
+    pub fn new(junctions: &'a [Point<D>], metric: M) -> Self {
+        let mut pairs = Self {
+            tree: KdTree::new(junctions, metric),
+            neighbors: Vec::with_capacity(junctions.len()),
+            queue: BinaryHeap::with_capacity(junctions.len()),
+        };
+
+        for junction in 0..junctions.len() {
+            let found = pairs.fetch(junction, Self::BATCH);
+            pairs.neighbors.push(Neighbors {
+                k: Self::BATCH,
+                found,
+                next: 0,
+            });
+            pairs.advance(junction);
+        }
+        pairs
+    }
+
+    /// Returns the `k` nearest neighbors with a larger index than the junction.
+    fn fetch(&self, junction: usize, k: usize) -> Vec<(u64, usize)> {
+        self.tree
+            .nearest(junction, k)
+            .into_iter()
+            .filter(|&(_, other)| other > junction)
+            .collect()
+    }
+
+    /// Queues the next pair of the junction, fetches more neighbors when all are used up.
+    fn advance(&mut self, junction: usize) {
+        let len = self.tree.junctions.len();
+        loop {
+            let neighbors = &mut self.neighbors[junction];
+            if let Some(&(distance, other)) = neighbors.found.get(neighbors.next) {
+                neighbors.next += 1;
+                self.queue.push(Reverse((distance, junction, other)));
+                return;
             }
-            (None, Some(r)) => {
-                circuits[r].push(lhs);
+            if neighbors.k >= len {
+                return;
+            }
+
+            // the nearest neighbors of a larger batch start with the ones already used
+            let k = (neighbors.k * 2).min(len);
+            let found = self.fetch(junction, k);
+            let neighbors = &mut self.neighbors[junction];
+            neighbors.k = k;
+            neighbors.found = found;
+        }
+    }
+}
+
+impl<const D: usize, M: Metric> Iterator for NearestPairs<'_, D, M> {
+    type Item = (u64, Pair);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((distance, lhs, rhs)) = self.queue.pop()?;
+        self.advance(lhs);
+        Some((distance, (lhs, rhs)))
+    }
+}
+
+/// Computes the minimum spanning tree of all junctions with Prim's algorithm.
+///
+/// Works on the complete graph without storing its edges, in quadratic time and linear space.
+/// Returns the edges in the order Kruskal's algorithm adds them, by distance, then by indices.
+fn spanning_tree<const D: usize>(junctions: &[Point<D>], metric: &impl Metric) -> Vec<(u64, Pair)> {
+    let len = junctions.len();
+    let mut edges = Vec::with_capacity(len.saturating_sub(1));
+    let mut in_tree = vec![false; len];
+    // the shortest edge connecting each junction to the tree
+    let mut best = vec![(u64::MAX, (usize::MAX, usize::MAX)); len];
+
+    let mut current = 0;
+    for _ in 1..len {
+        in_tree[current] = true;
+        let mut next: Option<usize> = None;
+        for other in (0..len).filter(|&other| !in_tree[other]) {
+            let pair = (current.min(other), current.max(other));
+            let distance = metric.distance(&junctions[current].coords, &junctions[other].coords);
+            let edge = (distance, pair);
+            if edge < best[other] {
+                best[other] = edge;
             }
-            (Some(l), Some(r)) => {
-                if l != r {
-                    let other = circuits[r].clone();
-                    circuits[l].extend(other);
-                    circuits.swap_remove(r);
-                    if circuits.len() == 1 {
-                        break;
-                    }
-                }
+            if next.is_none_or(|next| best[other] < best[next]) {
+                next = Some(other);
             }
-            _ => (),
         }
+
+        let Some(next) = next else { break };
+        edges.push(best[next]);
+        current = next;
     }
 
-    circuits
-        .into_iter()
-        .sorted_by(|l, r| r.len().cmp(&l.len()))
-        .take(largest)
-        .map(|circuit| circuit.len() as u64)
-        .product()
+    edges.sort_unstable();
+    edges
 }
 
-fn process_part2(junctions: &[Vec3]) -> u64 {
-    let pairings = junctions
-        .iter()
-        .tuple_combinations()
-        .map(|(l, r)| (l.distance_squared(r), (l, r)))
-        .sorted_by(|l, r| l.0.partial_cmp(&r.0).unwrap())
-        .collect::<Vec<_>>();
+/// The junctions with the connections between them, in the order they were made.
+struct Network<'a, const D: usize> {
+    junctions: &'a [Point<D>],
+    edges: Vec<(u64, Pair)>,
+}
+
+impl<'a, const D: usize> Network<'a, D> {
+    /// Connects the given number of nearest pairs, including pairs already sharing a circuit.
+    pub fn connect(junctions: &'a [Point<D>], num_pairs: usize) -> Self {
+        Self::connect_with(junctions, num_pairs, Euclidean)
+    }
+
+    /// Connects the given number of nearest pairs, measured by the metric.
+    pub fn connect_with(junctions: &'a [Point<D>], num_pairs: usize, metric: impl Metric) -> Self {
+        let edges = NearestPairs::new(junctions, metric)
+            .take(num_pairs)
+            .collect();
+        Self { junctions, edges }
+    }
+
+    /// Connects all junctions by the edges of the minimum spanning tree.
+    pub fn spanning_tree(junctions: &'a [Point<D>]) -> Self {
+        Self::spanning_tree_with(junctions, Euclidean)
+    }
+
+    /// Connects all junctions by the edges of the minimum spanning tree under the metric.
+    pub fn spanning_tree_with(junctions: &'a [Point<D>], metric: impl Metric) -> Self {
+        let edges = spanning_tree(junctions, &metric);
+        Self { junctions, edges }
+    }
 
-    let mut last_merged_pair: Option<(&Vec3, &Vec3)> = None;
-    let mut circuits: Vec<Vec<&Vec3>> = junctions.iter().map(|j| vec![j]).collect_vec();
+    /// Returns the connections with their distances, in the order they were made.
+    pub fn edges(&self) -> &[(u64, Pair)] {
+        &self.edges
+    }
 
-    for (_, (lhs, rhs)) in pairings.into_iter() {
-        let left = circuits.iter().position(|c| c.contains(&lhs));
-        let right = circuits.iter().position(|c| c.contains(&rhs));
+    /// Returns the circuits formed by the connections.
+    pub fn circuits(&self) -> Circuits {
+        let mut circuits = Circuits::new(self.junctions.len());
+        for (_, (lhs, rhs)) in self.edges.iter() {
+            circuits.union(*lhs, *rhs);
+        }
+        circuits
+    }
+
+    /// Writes the connections as CSV, one line per connection in the order they were made.
+    pub fn write_csv(&self, out: &mut impl Write) -> anyhow::Result<()> {
+        let axes = (0..D).map(Self::axis_name).collect::<Vec<_>>();
+        let mut columns = ["lhs", "rhs"]
+            .iter()
+            .flat_map(|side| axes.iter().map(move |axis| format!("{}_{}", side, axis)));
+        writeln!(out, "order,lhs,rhs,{},distance", columns.join(","))?;
 
-        match (left, right) {
-            (Some(l), None) => {
-                circuits[l].push(rhs);
+        for (order, (distance, (lhs, rhs))) in self.edges.iter().enumerate() {
+            let mut coords = self.junctions[*lhs]
+                .coords
+                .iter()
+                .chain(self.junctions[*rhs].coords.iter());
+            writeln!(
+                out,
+                "{},{},{},{},{}",
+                order + 1,
+                lhs,
+                rhs,
+                coords.join(","),
+                distance
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the network as Graphviz DOT graph, each circuit of several junctions is drawn
+    /// as a cluster, the connections are labelled with their order.
+    pub fn write_dot(&self, out: &mut impl Write) -> anyhow::Result<()> {
+        writeln!(out, "graph junctions {{")?;
+        for (index, members) in self.circuits().members().iter().enumerate() {
+            let cluster = members.len() > 1;
+            if cluster {
+                writeln!(out, "  subgraph cluster_{} {{", index)?;
             }
-            (None, Some(r)) => {
-                circuits[r].push(lhs);
+            for junction in members {
+                let label = self.junctions[*junction].coords.iter().join(",");
+                let indent = if cluster { "    " } else { "  " };
+                writeln!(out, "{}{} [label=\"{}\"];", indent, junction, label)?;
             }
-            (Some(l), Some(r)) => {
-                if l != r {
-                    let other = circuits[r].clone();
-                    circuits[l].extend(other);
-                    circuits.swap_remove(r);
-                    if circuits.len() == 1 {
-                        last_merged_pair = Some((lhs, rhs));
-                        break;
-                    }
-                }
+            if cluster {
+                writeln!(out, "  }}")?;
             }
-            _ => (),
         }
+        for (order, (_, (lhs, rhs))) in self.edges.iter().enumerate() {
+            writeln!(out, "  {} -- {} [label=\"{}\"];", lhs, rhs, order + 1)?;
+        }
+        writeln!(out, "}}")?;
+        Ok(())
     }
 
-    if let Some((l, r)) = last_merged_pair {
-        l.x as u64 * r.x as u64
-    } else {
-        0
+    /// Names the axes x, y, z and w, further ones by their index.
+    fn axis_name(axis: usize) -> String {
+        match axis {
+            0..4 => ["x", "y", "z", "w"][axis].to_string(),
+            _ => format!("a{}", axis),
+        }
+    }
+}
+
+/// Connect junctions by shortest distance, group them into circuits.
+///
+/// Keep list of all circuits, stop when the given number of connections have been reached.
+fn process_part1(junctions: &[Vec3], num_pairs: usize, largest: usize) -> u64 {
+    Network::connect(junctions, num_pairs)
+        .circuits()
+        .sizes()
+        .into_iter()
+        .take(largest)
+        .map(|size| size as u64)
+        .product()
+}
+
+/// The pair closing the last two circuits is the longest edge of the minimum spanning tree.
+fn process_part2(junctions: &[Vec3]) -> u64 {
+    match spanning_tree(junctions, &Euclidean).last() {
+        Some(&(_, (lhs, rhs))) => junctions[lhs].axis(0) as u64 * junctions[rhs].axis(0) as u64,
+        None => 0,
     }
 }
 
 fn main() {
     let junctions = parse(include_str!("input.txt"));
+    for indices in duplicates(&junctions) {
+        eprintln!(
+            "Duplicate junctions at {}: {:?}",
+            junctions[indices[0]], indices
+        );
+    }
+
     let result = process_part1(&junctions, 1000, 3);
     println!("PART 1: {}", result);
+    let cutoff = Cutoff::find(&junctions, 1000, Euclidean);
+    if let Some(cutoff) = cutoff.as_ref().filter(|cutoff| cutoff.is_ambiguous()) {
+        eprintln!(
+            "PART 1 is ambiguous, another choice of the {} tied pairs changes the circuits",
+            cutoff.pairs().count()
+        );
+    }
     let result = process_part2(&junctions);
     println!("PART 2: {}", result);
+
+    // `--ties` lists the pairs tied at the part 1 cutoff
+    if std::env::args().nth(1).is_some_and(|arg| arg == "--ties") {
+        if let Some(cutoff) = cutoff {
+            for (lhs, rhs) in cutoff.taken.iter() {
+                println!("{} {} {} connected", cutoff.distance, lhs, rhs);
+            }
+            for (lhs, rhs) in cutoff.skipped.iter() {
+                println!("{} {} {} skipped", cutoff.distance, lhs, rhs);
+            }
+        }
+        return;
+    }
+
+    // `--csv` or `--dot` exports the spanning tree, or the given number of nearest connections
+    if let Some(format) = std::env::args().nth(1) {
+        let network = match std::env::args().nth(2) {
+            Some(num_pairs) => {
+                let num_pairs = num_pairs.parse().expect("Failed to parse number of pairs");
+                Network::connect(&junctions, num_pairs)
+            }
+            None => Network::spanning_tree(&junctions),
+        };
+        let mut out = std::io::stdout().lock();
+        let result = match format.as_str() {
+            "--csv" => network.write_csv(&mut out),
+            "--dot" => network.write_dot(&mut out),
+            _ => Err(anyhow::anyhow!("Unknown export format '{}'", format)),
+        };
+        if let Err(error) = result {
+            eprintln!("{:#}", error);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{parse, process_part1, process_part2};
+    use itertools::Itertools;
+
+    use crate::{
+        Chebyshev, Circuits, Cutoff, Euclidean, KdTree, Manhattan, Metric, NearestPairs, Network,
+        Point, duplicates, parse, parse_points, process_part1, process_part2, sorted_pairs,
+        spanning_tree,
+    };
 
     const INPUT: &str = r#"
 162,817,812
@@ -226,4 +828,225 @@ mod tests {
         let junctions = parse(INPUT);
         assert_eq!(25272, process_part2(&junctions));
     }
+
+    #[test]
+    fn test_circuits() {
+        let mut circuits = Circuits::new(6);
+        assert_eq!(6, circuits.len());
+        assert!(circuits.union(0, 1));
+        assert!(circuits.union(2, 3));
+        assert!(circuits.union(1, 3));
+        assert!(!circuits.union(0, 2));
+
+        assert!(circuits.connected(0, 3));
+        assert!(!circuits.connected(0, 4));
+        assert_eq!(4, circuits.size(2));
+        assert_eq!(3, circuits.len());
+        assert_eq!(vec![4, 1, 1], circuits.sizes());
+        assert_eq!(vec![vec![0, 1, 2, 3], vec![4], vec![5]], circuits.members());
+    }
+
+    #[test]
+    fn test_kd_tree_nearest() {
+        let junctions = parse(INPUT);
+        let tree = KdTree::new(&junctions, Euclidean);
+        for junction in 0..junctions.len() {
+            let expected = (0..junctions.len())
+                .map(|other| {
+                    (
+                        junctions[junction].distance_squared(&junctions[other]),
+                        other,
+                    )
+                })
+                .sorted()
+                .take(5)
+                .collect::<Vec<_>>();
+            assert_eq!(expected, tree.nearest(junction, 5));
+        }
+    }
+
+    /// Generates junctions in a small cube, many pairs share the same distance.
+    fn generate_junctions<const D: usize>(len: usize, size: u64) -> Vec<Point<D>> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % size) as i64
+        };
+        (0..len)
+            .map(|_| Point::new(std::array::from_fn(|_| next())))
+            .collect()
+    }
+
+    #[test]
+    fn test_nearest_pairs() {
+        for junctions in [parse(INPUT), generate_junctions(300, 12)] {
+            let pairs = NearestPairs::new(&junctions, Euclidean).collect::<Vec<_>>();
+            assert_eq!(sorted_pairs(&junctions, &Euclidean), pairs);
+        }
+    }
+
+    #[test]
+    fn test_spanning_tree() {
+        for junctions in [parse(INPUT), generate_junctions(300, 12)] {
+            check_spanning_tree(&junctions, Euclidean);
+        }
+    }
+
+    /// Checks the minimum spanning tree against Kruskal's algorithm on all sorted pairs.
+    fn check_spanning_tree<const D: usize>(junctions: &[Point<D>], metric: impl Metric) {
+        let edges = spanning_tree(junctions, &metric);
+        assert_eq!(junctions.len() - 1, edges.len());
+
+        let mut circuits = Circuits::new(junctions.len());
+        let expected = sorted_pairs(junctions, &metric)
+            .into_iter()
+            .filter(|(_, (lhs, rhs))| circuits.union(*lhs, *rhs))
+            .collect::<Vec<_>>();
+        assert_eq!(expected, edges);
+    }
+
+    /// Checks the lazily generated pairs against all sorted pairs.
+    fn check_nearest_pairs<const D: usize>(junctions: &[Point<D>], metric: impl Metric + Copy) {
+        let pairs = NearestPairs::new(junctions, metric).collect::<Vec<_>>();
+        assert_eq!(sorted_pairs(junctions, &metric), pairs);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let lhs = Point::new([1, -2, 3]);
+        let rhs = Point::new([4, 2, 3]);
+        assert_eq!(25, Euclidean.distance(&lhs.coords, &rhs.coords));
+        assert_eq!(7, Manhattan.distance(&lhs.coords, &rhs.coords));
+        assert_eq!(4, Chebyshev.distance(&lhs.coords, &rhs.coords));
+        assert_eq!(5.0, lhs.distance(&rhs));
+    }
+
+    #[test]
+    fn test_metrics_other_dimensions() {
+        let plane = generate_junctions::<2>(200, 16);
+        check_nearest_pairs(&plane, Manhattan);
+        check_nearest_pairs(&plane, Chebyshev);
+        check_spanning_tree(&plane, Manhattan);
+
+        let space = generate_junctions::<4>(200, 8);
+        check_nearest_pairs(&space, Euclidean);
+        check_nearest_pairs(&space, Chebyshev);
+        check_spanning_tree(&space, Chebyshev);
+
+        // a function without a bound for the tree, the distance along the y axis only
+        let vertical = |lhs: &[i64], rhs: &[i64]| lhs[1].abs_diff(rhs[1]);
+        check_nearest_pairs(&plane, vertical);
+        check_spanning_tree(&space, vertical);
+    }
+
+    #[test]
+    fn test_parse_points() {
+        let points = parse_points::<2>("1,2\n-3, 4\n");
+        assert_eq!(vec![Point::new([1, 2]), Point::new([-3, 4])], points);
+        assert!("1,2,3".parse::<Point<2>>().is_err());
+        assert!("1,2,3,x".parse::<Point<4>>().is_err());
+
+        let network = Network::connect_with(&points, 1, Manhattan);
+        let mut out = Vec::new();
+        network.write_csv(&mut out).unwrap();
+        let expected = "order,lhs,rhs,lhs_x,lhs_y,rhs_x,rhs_y,distance\n1,0,1,1,2,-3,4,6\n";
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn test_network_circuits() {
+        let junctions = parse(INPUT);
+        let network = Network::connect(&junctions, 10);
+        assert_eq!(10, network.edges().len());
+        assert_eq!((0, 19), network.edges()[0].1);
+
+        let mut circuits = network.circuits();
+        assert_eq!(11, circuits.len());
+        assert_eq!(vec![5, 4, 2, 2], circuits.sizes()[..4]);
+        assert!(circuits.connected(0, 19));
+
+        let network = Network::spanning_tree(&junctions);
+        assert_eq!(19, network.edges().len());
+        assert_eq!(1, network.circuits().len());
+    }
+
+    #[test]
+    fn test_network_write_csv() {
+        let junctions = parse(INPUT);
+        let mut out = Vec::new();
+        Network::connect(&junctions, 2).write_csv(&mut out).unwrap();
+        let expected = "\
+order,lhs,rhs,lhs_x,lhs_y,lhs_z,rhs_x,rhs_y,rhs_z,distance
+1,0,19,162,817,812,425,690,689,100427
+2,0,7,162,817,812,431,825,988,103401
+";
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn test_network_write_dot() {
+        let junctions = parse(&INPUT.lines().take(4).join("\n"));
+        assert_eq!(3, junctions.len());
+        let mut out = Vec::new();
+        Network::connect(&junctions, 1).write_dot(&mut out).unwrap();
+        let expected = r#"graph junctions {
+  subgraph cluster_0 {
+    0 [label="162,817,812"];
+    1 [label="57,618,57"];
+  }
+  2 [label="906,360,560"];
+  0 -- 1 [label="1"];
+}
+"#;
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let junctions = parse_points::<2>("1,1\n2,2\n1,1\n3,3\n2,2\n1,1\n");
+        assert_eq!(vec![vec![0, 2, 5], vec![1, 4]], duplicates(&junctions));
+        assert!(duplicates(&parse(INPUT)).is_empty());
+    }
+
+    #[test]
+    fn test_cutoff() {
+        let junctions = parse(INPUT);
+        let cutoff = Cutoff::find(&junctions, 10, Euclidean).unwrap();
+        assert!(!cutoff.is_ambiguous());
+        assert_eq!(1, cutoff.pairs().count());
+        assert_eq!(None, Cutoff::find(&junctions, 0, Euclidean));
+
+        // the unit square has four sides of the same length, any three connect all corners
+        let square = parse_points::<2>("0,0\n0,1\n1,0\n1,1\n");
+        let cutoff = Cutoff::find(&square, 3, Euclidean).unwrap();
+        let expected = Cutoff {
+            distance: 1,
+            taken: vec![(0, 1), (0, 2), (1, 3)],
+            skipped: vec![(2, 3)],
+            ambiguous: false,
+        };
+        assert_eq!(expected, cutoff);
+        assert!(Cutoff::find(&square, 2, Euclidean).unwrap().is_ambiguous());
+        assert!(!Cutoff::find(&square, 4, Euclidean).unwrap().is_ambiguous());
+
+        // either side of the line makes a circuit of two junctions
+        let line = parse_points::<2>("0,0\n1,0\n2,0\n");
+        assert!(!Cutoff::find(&line, 1, Euclidean).unwrap().is_ambiguous());
+
+        // a line of three junctions and a separate pair, the pair could be connected instead
+        let junctions = parse_points::<2>("0,0\n1,0\n2,0\n10,0\n11,0\n");
+        let cutoff = Cutoff::find(&junctions, 2, Euclidean).unwrap();
+        assert_eq!(vec![(0, 1), (1, 2)], cutoff.taken);
+        assert_eq!(vec![(3, 4)], cutoff.skipped);
+        assert!(cutoff.is_ambiguous());
+
+        // a path connecting all junctions, the skipped chord closes a triangle instead
+        let junctions = parse_points::<3>("1,1,0\n2,-1,1\n0,0,0\n1,0,1\n");
+        let cutoff = Cutoff::find(&junctions, 3, Euclidean).unwrap();
+        assert_eq!(vec![(0, 2), (0, 3), (1, 3)], cutoff.taken);
+        assert_eq!(vec![(2, 3)], cutoff.skipped);
+        assert!(cutoff.is_ambiguous());
+    }
 }